@@ -0,0 +1,36 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::{fmt::Write, hint::black_box};
+use syntax::{
+    lexer::{Lexer, LexerConfig},
+    span::SourceId,
+};
+
+/// A few thousand declarations, each line indented by `indent` spaces.
+fn generate_source(indent: usize) -> String {
+    let pad = " ".repeat(indent);
+    let mut src = String::new();
+    for i in 0..2_000 {
+        writeln!(
+            src,
+            "fun function_{i} (argument : int) : int =\n\
+             {pad}let val total_{i} = argument * {i} + 0x{i:x}\n\
+             {pad}in if total_{i} > 100 then total_{i} else ~1 end\n\n"
+        )
+        .unwrap();
+    }
+    src
+}
+
+fn bench_tokenize(c: &mut Criterion) {
+    for (name, indent) in [("tokenize", 4), ("tokenize_whitespace_heavy", 200)] {
+        let src = generate_source(indent);
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                Lexer::new(SourceId::default(), black_box(&src), LexerConfig::default()).tokenize()
+            })
+        });
+    }
+}
+
+criterion_group!(benches, bench_tokenize);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use std::fmt::Write;
+use syntax::{
+    lexer::{Lexer, LexerConfig},
+    parser::Parser,
+    span::SourceId,
+};
+
+/// A few thousand declarations mixing the common expression forms.
+fn generate_source() -> String {
+    let mut src = String::new();
+    for i in 0..2_000 {
+        writeln!(
+            src,
+            "fun function_{i} (argument : int) (flag : bool) : int =\n\
+             \x20   let val total_{i} = argument * {i} + (argument - 1) * 2\n\
+             \x20       val pair_{i} = (total_{i}, argument - {i}, 3)\n\
+             \x20   in if flag andalso total_{i} > 100 then total_{i} else ~1 end\n\n\
+             val value_{i} : int = function_{i} {i} true\n"
+        )
+        .unwrap();
+    }
+    src
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let src = generate_source();
+    let tokens = Lexer::new(SourceId::default(), &src, LexerConfig::default())
+        .tokenize()
+        .unwrap();
+    c.bench_function("parse_program", |b| {
+        b.iter_batched(
+            || tokens.clone(),
+            |tokens| Parser::new(tokens).parse_program().unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -1,9 +1,21 @@
-use std::fmt::Display;
+//! The abstract syntax tree produced by the parser.
+//!
+//! Every child node that a diagnostic may need to point at (sub-expressions,
+//! statements, names, operators, type annotations and parameters) is stored as
+//! a [`Spanned`] pair, and a node's own span always lives in the pair that
+//! holds it rather than inside the node. `Type` is the one exception: the parts
+//! of a compound type are plain `Type`s, and only the annotation as a whole is
+//! spanned.
 
-use crate::span::Spanned;
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    SyntaxError,
+    span::{SourceId, Span, Spanned},
+};
 use internment::Intern;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Ident(pub Intern<String>);
 
 impl Display for Ident {
@@ -12,19 +24,52 @@ impl Display for Ident {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for Ident {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_ref())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Ident {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Ident::from)
+    }
+}
+
+impl From<&str> for Ident {
+    fn from(name: &str) -> Self {
+        Ident(Intern::from_ref(name))
+    }
+}
+
+impl From<String> for Ident {
+    fn from(name: String) -> Self {
+        Ident(Intern::new(name))
+    }
+}
+
 impl AsRef<String> for Ident {
     fn as_ref(&self) -> &String {
         self.0.as_ref()
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
     Int,
     Char,
     Bool,
     Real,
     Unit,
+    Tuple(Vec<Type>),
+    /// `ty list`, written postfix as in SML.
+    List(Box<Type>),
+    /// A type variable such as `'a`, named without its quote.
+    Var(Ident),
+    Arrow(Box<Type>, Box<Type>),
 }
 
 impl Display for Type {
@@ -35,14 +80,52 @@ impl Display for Type {
             Type::Bool => "bool",
             Type::Real => "real",
             Type::Unit => "()",
+            Type::Var(name) => return write!(f, "'{name}"),
+            Type::Tuple(tys) => {
+                f.write_str("(")?;
+                for (i, ty) in tys.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{ty}")?;
+                }
+                return f.write_str(")");
+            }
+            Type::List(elem) => {
+                // `list` binds tighter than `->`, so an arrow element needs parentheses.
+                return match elem.as_ref() {
+                    Type::Arrow(..) => write!(f, "({elem}) list"),
+                    _ => write!(f, "{elem} list"),
+                };
+            }
+            Type::Arrow(param, ret) => {
+                // Arrows are right-associative, so only a left operand needs parentheses.
+                return match param.as_ref() {
+                    Type::Arrow(..) => write!(f, "({param}) -> {ret}"),
+                    _ => write!(f, "{param} -> {ret}"),
+                };
+            }
         };
         f.write_str(s)
     }
 }
 
+/// Parses the syntax produced by `Display`, e.g. `"int -> (bool, real)"`.
+impl FromStr for Type {
+    type Err = Vec<SyntaxError>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        crate::tokenize(SourceId::default(), s)?
+            .parse_type_code()
+            .map(|(ty, _)| ty)
+            .map_err(|error| vec![SyntaxError::from(error)])
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Literal {
-    Int(usize),
+    Int(i64),
     Char(char),
     Bool(bool),
     Real(f64),
@@ -52,22 +135,29 @@ pub enum Literal {
 impl Display for Literal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Literal::Int(v) if *v < 0 => write!(f, "~{}", v.unsigned_abs()),
             Literal::Int(v) => write!(f, "{v}"),
-            Literal::Char(c) => write!(f, "'{c}'"),
+            Literal::Char(c) => crate::lexer::write_char_literal(f, *c),
             Literal::Bool(b) => write!(f, "{b}"),
-            Literal::Real(x) => write!(f, "{x}"),
+            // `Debug` gives the shortest representation that round-trips and always
+            // keeps a `.` or exponent, so the lexer reads it back as a real.
+            Literal::Real(x) if x.is_sign_negative() => write!(f, "~{:?}", -x),
+            Literal::Real(x) => write!(f, "{x:?}"),
             Literal::Unit => write!(f, "()"),
         }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOp {
     Add,
     Sub,
     Mul,
     Div,
     Rem,
+    /// `^`, which parses but is not yet evaluated or folded.
+    Pow,
 
     Eq,
     NotEq,
@@ -76,17 +166,29 @@ pub enum BinaryOp {
     Greater,
     GreaterEq,
 
-    And,
-    Or,
+    // Short-circuiting: the right operand is only evaluated when needed.
+    LogicalAnd,
+    LogicalOr,
+
+    Cons,
+}
+
+impl BinaryOp {
+    /// Whether the right operand may be skipped depending on the left one.
+    pub fn is_short_circuit(self) -> bool {
+        matches!(self, BinaryOp::LogicalAnd | BinaryOp::LogicalOr)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryOp {
     Neg,
     Not,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BorrowOp {
     Ref,    // &x
     RefMut, // &mut x
@@ -100,6 +202,7 @@ impl Display for BinaryOp {
             BinaryOp::Mul => "*",
             BinaryOp::Div => "div",
             BinaryOp::Rem => "mod",
+            BinaryOp::Pow => "^",
 
             BinaryOp::Eq => "=",
             BinaryOp::NotEq => "<>",
@@ -108,8 +211,10 @@ impl Display for BinaryOp {
             BinaryOp::Greater => ">",
             BinaryOp::GreaterEq => ">=",
 
-            BinaryOp::And => "&&",
-            BinaryOp::Or => "||",
+            BinaryOp::LogicalAnd => "&&",
+            BinaryOp::LogicalOr => "||",
+
+            BinaryOp::Cons => "::",
         };
         f.write_str(s)
     }
@@ -136,9 +241,13 @@ impl Display for BorrowOp {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Literal(Literal),
     Local(Ident),
+    Tuple(Vec<Spanned<Expr>>),
+    /// `[e1, e2, ...]`, where `[]` is the empty list.
+    List(Vec<Spanned<Expr>>),
     Unary {
         op: Spanned<UnaryOp>,
         expr: Box<Spanned<Expr>>,
@@ -160,23 +269,148 @@ pub enum Expr {
         stmts: Vec<Spanned<Stmt>>,
         expr: Box<Spanned<Self>>,
     },
+    /// `(s1; s2; e)`: run each statement in order, then evaluate to `e`.
+    Seq {
+        stmts: Vec<Spanned<Stmt>>,
+        expr: Box<Spanned<Self>>,
+    },
     If {
         condition: Box<Spanned<Self>>,
         then_expr: Box<Spanned<Expr>>,
         else_expr: Box<Spanned<Self>>,
     },
+    /// `e : ty`, asserting that `e` has type `ty`.
+    Ascription {
+        expr: Box<Spanned<Self>>,
+        ty: Spanned<Type>,
+    },
+    /// `case e of p1 => e1 | p2 => e2`, taking the first arm whose pattern
+    /// matches.
+    Case {
+        scrutinee: Box<Spanned<Self>>,
+        arms: Vec<Arm>,
+    },
 }
 
+/// A `case` pattern. Patterns do not nest yet.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pattern {
+    /// `_`, which matches anything.
+    Wildcard,
+    Literal(Literal),
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => f.write_str("_"),
+            Pattern::Literal(literal) => write!(f, "{literal}"),
+        }
+    }
+}
+
+/// One `pattern => expr` arm of a `case`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Arm {
+    pub pattern: Spanned<Pattern>,
+    pub expr: Spanned<Expr>,
+}
+
+/// Renders the expression fully parenthesized, which makes precedence explicit.
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            // Negative literals only come from folding `~`, so they are
+            // parenthesized like the unary they replaced.
+            Expr::Literal(lit) => match lit {
+                Literal::Int(v) if *v < 0 => write!(f, "({lit})"),
+                Literal::Real(x) if x.is_sign_negative() => write!(f, "({lit})"),
+                _ => write!(f, "{lit}"),
+            },
+            Expr::Local(ident) => write!(f, "{ident}"),
+            Expr::Tuple(elems) => {
+                f.write_str("(")?;
+                for (i, (elem, _)) in elems.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{elem}")?;
+                }
+                f.write_str(")")
+            }
+            Expr::List(elems) => {
+                f.write_str("[")?;
+                for (i, (elem, _)) in elems.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{elem}")?;
+                }
+                f.write_str("]")
+            }
+            Expr::Unary { op, expr } => match op.0 {
+                UnaryOp::Neg => write!(f, "({}{})", op.0, expr.0),
+                UnaryOp::Not => write!(f, "({} {})", op.0, expr.0),
+            },
+            Expr::Borrow { op, expr } => match op.0 {
+                BorrowOp::Ref => write!(f, "({}{})", op.0, expr.0),
+                BorrowOp::RefMut => write!(f, "({} {})", op.0, expr.0),
+            },
+            Expr::Apply { callee, arg } => write!(f, "({} {})", callee.0, arg.0),
+            Expr::Binary { left, op, right } => write!(f, "({} {} {})", left.0, op.0, right.0),
+            Expr::Let { stmts, expr } => {
+                f.write_str("let ")?;
+                for (i, (stmt, _)) in stmts.iter().enumerate() {
+                    let sep = if i + 1 == stmts.len() { " " } else { "; " };
+                    write!(f, "{stmt}{sep}")?;
+                }
+                write!(f, "in {} end", expr.0)
+            }
+            Expr::Seq { stmts, expr } => {
+                f.write_str("(")?;
+                for (stmt, _) in stmts {
+                    write!(f, "{stmt}; ")?;
+                }
+                write!(f, "{})", expr.0)
+            }
+            Expr::If {
+                condition,
+                then_expr,
+                else_expr,
+            } => write!(
+                f,
+                "(if {} then {} else {})",
+                condition.0, then_expr.0, else_expr.0
+            ),
+            Expr::Ascription { expr, ty } => write!(f, "({} : {})", expr.0, ty.0),
+            Expr::Case { scrutinee, arms } => {
+                write!(f, "(case {} of", scrutinee.0)?;
+                for (i, arm) in arms.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { " |" };
+                    write!(f, "{sep} {} => {}", arm.pattern.0, arm.expr.0)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Val {
-    name: Spanned<Ident>,
-    ty: Option<Type>,
-    expr: Spanned<Expr>,
+    pub name: Spanned<Ident>,
+    pub ty: Option<Spanned<Type>>,
+    pub expr: Spanned<Expr>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Stmt {
     Val(Val),
+    /// A local function, as in `let fun f x = ... in ... end`.
+    Fun(Func),
     Assign {
         target: Spanned<Ident>,
         value: Spanned<Expr>,
@@ -185,15 +419,46 @@ pub enum Stmt {
         condition: Spanned<Expr>,
         body: Box<Spanned<Stmt>>,
     },
+    /// An expression evaluated only for its effect, as in `(f x; y)`.
+    Expr(Spanned<Expr>),
+}
+
+impl Display for Val {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "val {}", self.name.0)?;
+        if let Some((ty, _)) = &self.ty {
+            write!(f, " : {ty}")?;
+        }
+        write!(f, " = {}", self.expr.0)
+    }
+}
+
+impl Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stmt::Val(val) => write!(f, "{val}"),
+            Stmt::Fun(func) => write!(f, "{func}"),
+            Stmt::Assign { target, value } => write!(f, "{} := {}", target.0, value.0),
+            Stmt::While { condition, body } => {
+                write!(f, "while {} do {}", condition.0, body.0)
+            }
+            Stmt::Expr((expr, _)) => write!(f, "{expr}"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FuncParam {
     Ident(Ident),
-    Typed { param: Box<FuncParam>, ty: Type },
+    Typed {
+        param: Box<Spanned<FuncParam>>,
+        ty: Spanned<Type>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Func {
     pub name: Spanned<Ident>,
     pub params: Vec<Spanned<FuncParam>>,
@@ -202,7 +467,482 @@ pub struct Func {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Decl {
     Val(Val),
     Func(Func),
+    /// `fun f ... and g ...`: functions that may call each other. Always holds
+    /// at least two functions; a lone `fun` is a [`Decl::Func`].
+    FuncGroup(Vec<Func>),
+}
+
+impl Display for FuncParam {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FuncParam::Ident(ident) => write!(f, "{ident}"),
+            FuncParam::Typed { param, ty } => write!(f, "({} : {})", param.0, ty.0),
+        }
+    }
+}
+
+impl Display for Func {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("fun ")?;
+        self.fmt_clause(f)
+    }
+}
+
+impl Func {
+    /// Everything after the leading `fun` or `and`.
+    fn fmt_clause(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name.0)?;
+        for (param, _) in &self.params {
+            write!(f, " {param}")?;
+        }
+        if let Some((ty, _)) = &self.ty {
+            write!(f, " : {ty}")?;
+        }
+        write!(f, " = {}", self.expr.0)
+    }
+}
+
+impl Display for Decl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Decl::Val(val) => write!(f, "{val}"),
+            Decl::Func(func) => write!(f, "{func}"),
+            Decl::FuncGroup(funcs) => {
+                for (i, func) in funcs.iter().enumerate() {
+                    f.write_str(if i == 0 { "fun " } else { " and " })?;
+                    func.fmt_clause(f)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn all_structurally_eq<T>(a: &[Spanned<T>], b: &[Spanned<T>], eq: impl Fn(&T, &T) -> bool) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| eq(&a.0, &b.0))
+}
+
+impl Expr {
+    /// Compare the shape of two expressions, ignoring every embedded span.
+    pub fn structurally_eq(&self, other: &Expr) -> bool {
+        match (self, other) {
+            (Expr::Literal(a), Expr::Literal(b)) => a == b,
+            (Expr::Local(a), Expr::Local(b)) => a == b,
+            (Expr::Tuple(a), Expr::Tuple(b)) => all_structurally_eq(a, b, Expr::structurally_eq),
+            (Expr::List(a), Expr::List(b)) => all_structurally_eq(a, b, Expr::structurally_eq),
+            (Expr::Unary { op: a_op, expr: a }, Expr::Unary { op: b_op, expr: b }) => {
+                a_op.0 == b_op.0 && a.0.structurally_eq(&b.0)
+            }
+            (Expr::Borrow { op: a_op, expr: a }, Expr::Borrow { op: b_op, expr: b }) => {
+                a_op.0 == b_op.0 && a.0.structurally_eq(&b.0)
+            }
+            (
+                Expr::Apply {
+                    callee: a_callee,
+                    arg: a_arg,
+                },
+                Expr::Apply {
+                    callee: b_callee,
+                    arg: b_arg,
+                },
+            ) => a_callee.0.structurally_eq(&b_callee.0) && a_arg.0.structurally_eq(&b_arg.0),
+            (
+                Expr::Binary {
+                    left: a_left,
+                    op: a_op,
+                    right: a_right,
+                },
+                Expr::Binary {
+                    left: b_left,
+                    op: b_op,
+                    right: b_right,
+                },
+            ) => {
+                a_op.0 == b_op.0
+                    && a_left.0.structurally_eq(&b_left.0)
+                    && a_right.0.structurally_eq(&b_right.0)
+            }
+            (
+                Expr::Let {
+                    stmts: a_stmts,
+                    expr: a,
+                },
+                Expr::Let {
+                    stmts: b_stmts,
+                    expr: b,
+                },
+            ) => {
+                all_structurally_eq(a_stmts, b_stmts, Stmt::structurally_eq)
+                    && a.0.structurally_eq(&b.0)
+            }
+            (
+                Expr::Seq {
+                    stmts: a_stmts,
+                    expr: a,
+                },
+                Expr::Seq {
+                    stmts: b_stmts,
+                    expr: b,
+                },
+            ) => {
+                all_structurally_eq(a_stmts, b_stmts, Stmt::structurally_eq)
+                    && a.0.structurally_eq(&b.0)
+            }
+            (
+                Expr::If {
+                    condition: a_cond,
+                    then_expr: a_then,
+                    else_expr: a_else,
+                },
+                Expr::If {
+                    condition: b_cond,
+                    then_expr: b_then,
+                    else_expr: b_else,
+                },
+            ) => {
+                a_cond.0.structurally_eq(&b_cond.0)
+                    && a_then.0.structurally_eq(&b_then.0)
+                    && a_else.0.structurally_eq(&b_else.0)
+            }
+            (Expr::Ascription { expr: a, ty: a_ty }, Expr::Ascription { expr: b, ty: b_ty }) => {
+                a_ty.0 == b_ty.0 && a.0.structurally_eq(&b.0)
+            }
+            (
+                Expr::Case {
+                    scrutinee: a,
+                    arms: a_arms,
+                },
+                Expr::Case {
+                    scrutinee: b,
+                    arms: b_arms,
+                },
+            ) => {
+                a.0.structurally_eq(&b.0)
+                    && a_arms.len() == b_arms.len()
+                    && a_arms.iter().zip(b_arms).all(|(a, b)| {
+                        a.pattern.0 == b.pattern.0 && a.expr.0.structurally_eq(&b.expr.0)
+                    })
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Val {
+    /// Compare two bindings, ignoring every embedded span.
+    pub fn structurally_eq(&self, other: &Val) -> bool {
+        self.name.0 == other.name.0
+            && self.ty.as_ref().map(|ty| &ty.0) == other.ty.as_ref().map(|ty| &ty.0)
+            && self.expr.0.structurally_eq(&other.expr.0)
+    }
+}
+
+impl Func {
+    /// Compare two functions, ignoring every embedded span.
+    pub fn structurally_eq(&self, other: &Func) -> bool {
+        self.name.0 == other.name.0
+            && all_structurally_eq(&self.params, &other.params, FuncParam::structurally_eq)
+            && self.ty.as_ref().map(|ty| &ty.0) == other.ty.as_ref().map(|ty| &ty.0)
+            && self.expr.0.structurally_eq(&other.expr.0)
+    }
+}
+
+impl Stmt {
+    /// Compare the shape of two statements, ignoring every embedded span.
+    pub fn structurally_eq(&self, other: &Stmt) -> bool {
+        match (self, other) {
+            (Stmt::Val(a), Stmt::Val(b)) => a.structurally_eq(b),
+            (Stmt::Fun(a), Stmt::Fun(b)) => a.structurally_eq(b),
+            (
+                Stmt::Assign {
+                    target: a_target,
+                    value: a_value,
+                },
+                Stmt::Assign {
+                    target: b_target,
+                    value: b_value,
+                },
+            ) => a_target.0 == b_target.0 && a_value.0.structurally_eq(&b_value.0),
+            (
+                Stmt::While {
+                    condition: a_cond,
+                    body: a_body,
+                },
+                Stmt::While {
+                    condition: b_cond,
+                    body: b_body,
+                },
+            ) => a_cond.0.structurally_eq(&b_cond.0) && a_body.0.structurally_eq(&b_body.0),
+            (Stmt::Expr(a), Stmt::Expr(b)) => a.0.structurally_eq(&b.0),
+            _ => false,
+        }
+    }
+}
+
+impl FuncParam {
+    /// Compare two parameters, ignoring every embedded span.
+    pub fn structurally_eq(&self, other: &FuncParam) -> bool {
+        match (self, other) {
+            (FuncParam::Ident(a), FuncParam::Ident(b)) => a == b,
+            (
+                FuncParam::Typed {
+                    param: a_param,
+                    ty: a_ty,
+                },
+                FuncParam::Typed {
+                    param: b_param,
+                    ty: b_ty,
+                },
+            ) => a_param.0.structurally_eq(&b_param.0) && a_ty.0 == b_ty.0,
+            _ => false,
+        }
+    }
+}
+
+impl Decl {
+    /// Compare the shape of two declarations, ignoring every embedded span.
+    pub fn structurally_eq(&self, other: &Decl) -> bool {
+        match (self, other) {
+            (Decl::Val(a), Decl::Val(b)) => a.structurally_eq(b),
+            (Decl::Func(a), Decl::Func(b)) => a.structurally_eq(b),
+            (Decl::FuncGroup(a), Decl::FuncGroup(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.structurally_eq(b))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Read-only traversal over the AST.
+///
+/// Every method defaults to walking the node's children, so implementors only
+/// override the nodes they care about and call the matching `walk_*` function
+/// to keep descending.
+pub trait Visitor: Sized {
+    fn visit_expr(&mut self, expr: &Spanned<Expr>) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Spanned<Stmt>) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_literal(&mut self, _literal: &Literal, _span: &Span) {}
+
+    fn visit_local(&mut self, _ident: &Ident, _span: &Span) {}
+
+    fn visit_binary(
+        &mut self,
+        left: &Spanned<Expr>,
+        _op: &Spanned<BinaryOp>,
+        right: &Spanned<Expr>,
+    ) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+
+    fn visit_apply(&mut self, callee: &Spanned<Expr>, arg: &Spanned<Expr>) {
+        self.visit_expr(callee);
+        self.visit_expr(arg);
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Spanned<Expr>,
+        then_expr: &Spanned<Expr>,
+        else_expr: &Spanned<Expr>,
+    ) {
+        self.visit_expr(condition);
+        self.visit_expr(then_expr);
+        self.visit_expr(else_expr);
+    }
+
+    fn visit_let(&mut self, stmts: &[Spanned<Stmt>], expr: &Spanned<Expr>) {
+        for stmt in stmts {
+            self.visit_stmt(stmt);
+        }
+        self.visit_expr(expr);
+    }
+}
+
+/// Dispatch `expr` to the matching [`Visitor`] method.
+pub fn walk_expr<V: Visitor>(visitor: &mut V, (expr, span): &Spanned<Expr>) {
+    match expr {
+        Expr::Literal(literal) => visitor.visit_literal(literal, span),
+        Expr::Local(ident) => visitor.visit_local(ident, span),
+        Expr::Tuple(elems) | Expr::List(elems) => {
+            for elem in elems {
+                visitor.visit_expr(elem);
+            }
+        }
+        Expr::Unary { expr, .. } | Expr::Borrow { expr, .. } => visitor.visit_expr(expr),
+        Expr::Apply { callee, arg } => visitor.visit_apply(callee, arg),
+        Expr::Binary { left, op, right } => visitor.visit_binary(left, op, right),
+        Expr::Let { stmts, expr } => visitor.visit_let(stmts, expr),
+        Expr::Seq { stmts, expr } => {
+            for stmt in stmts {
+                visitor.visit_stmt(stmt);
+            }
+            visitor.visit_expr(expr);
+        }
+        Expr::If {
+            condition,
+            then_expr,
+            else_expr,
+        } => visitor.visit_if(condition, then_expr, else_expr),
+        Expr::Ascription { expr, .. } => visitor.visit_expr(expr),
+        Expr::Case { scrutinee, arms } => {
+            visitor.visit_expr(scrutinee);
+            for arm in arms {
+                visitor.visit_expr(&arm.expr);
+            }
+        }
+    }
+}
+
+/// Visit the expressions and nested statements of `stmt`.
+pub fn walk_stmt<V: Visitor>(visitor: &mut V, (stmt, _): &Spanned<Stmt>) {
+    match stmt {
+        Stmt::Val(val) => visitor.visit_expr(&val.expr),
+        Stmt::Fun(func) => visitor.visit_expr(&func.expr),
+        Stmt::Assign { value, .. } | Stmt::Expr(value) => visitor.visit_expr(value),
+        Stmt::While { condition, body } => {
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(body);
+        }
+    }
+}
+
+/// The span of the smallest expression in `expr` that contains `offset`, e.g.
+/// the node under an editor's cursor.
+pub fn find_innermost(expr: &Spanned<Expr>, offset: usize) -> Option<Span> {
+    struct Innermost {
+        offset: usize,
+        found: Option<Span>,
+    }
+
+    impl Visitor for Innermost {
+        fn visit_expr(&mut self, expr: &Spanned<Expr>) {
+            // Children lie within their parent, so only descend on a hit.
+            if expr.1.contains(self.offset) {
+                self.found = Some(expr.1.clone());
+                walk_expr(self, expr);
+            }
+        }
+    }
+
+    let mut finder = Innermost {
+        offset,
+        found: None,
+    };
+    finder.visit_expr(expr);
+    finder.found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_find_innermost() {
+        let src = "f x + 2 * y";
+        let expr = crate::parse_str(0, src).unwrap();
+
+        let cursor = src.find('x').unwrap();
+        assert_eq!(find_innermost(&expr, cursor), Some(Span::new(0, 2..3)));
+        // The space between `f` and `x` belongs only to the application.
+        assert_eq!(find_innermost(&expr, 1), Some(Span::new(0, 0..3)));
+        // The operator belongs only to the whole sum.
+        assert_eq!(find_innermost(&expr, 4), Some(Span::new(0, 0..11)));
+        assert_eq!(find_innermost(&expr, src.len()), None);
+    }
+
+    #[test]
+    fn test_ident_from_str() {
+        let (expr, _) = crate::parse_str(0, "x").unwrap();
+        assert_eq!(expr, Expr::Local(Ident::from("x")));
+        assert_eq!(Ident::from("x"), Ident::from(String::from("x")));
+        assert_ne!(Ident::from("x"), Ident::from("y"));
+    }
+
+    #[test]
+    fn test_type_from_str() {
+        assert_eq!("int".parse::<Type>().unwrap(), Type::Int);
+        let ty = Type::Arrow(
+            Box::new(Type::Tuple(vec![Type::Int, Type::Char])),
+            Box::new(Type::Arrow(Box::new(Type::Bool), Box::new(Type::Unit))),
+        );
+        assert_eq!(ty.to_string().parse::<Type>().unwrap(), ty);
+
+        assert!("int extra".parse::<Type>().is_err());
+        assert!("".parse::<Type>().is_err());
+        assert!("int $".parse::<Type>().is_err());
+    }
+
+    #[test]
+    fn test_type_as_set_key() {
+        let mut types = HashSet::new();
+        types.insert(Type::Int);
+        types.insert(Type::Bool);
+        types.insert(Type::Arrow(Box::new(Type::Int), Box::new(Type::Bool)));
+        types.insert(Type::Int);
+
+        assert_eq!(types.len(), 3);
+        assert!(types.contains(&Type::Int));
+        assert!(types.contains(&Type::Bool));
+        assert!(types.contains(&Type::Arrow(Box::new(Type::Int), Box::new(Type::Bool))));
+        assert!(!types.contains(&Type::Real));
+    }
+
+    struct LiteralCounter(usize);
+
+    impl Visitor for LiteralCounter {
+        fn visit_literal(&mut self, _literal: &Literal, _span: &Span) {
+            self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn test_visitor_counts_literals() {
+        let expr = crate::parse_str(
+            0,
+            "let val x = 1 while x < 10 do x := x + 2 in if f (x, 'c') then 3.0 else () end",
+        )
+        .unwrap();
+        let mut counter = LiteralCounter(0);
+        counter.visit_expr(&expr);
+        assert_eq!(counter.0, 6);
+    }
+
+    #[test]
+    fn test_real_display_round_trips() {
+        for x in [
+            1.0,
+            0.5,
+            1e10,
+            1e300,
+            2.5e-8,
+            0.1 + 0.2,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+        ] {
+            let rendered = Literal::Real(x).to_string();
+            let tokens =
+                crate::lexer::Lexer::new(0, &rendered, crate::lexer::LexerConfig::default())
+                    .tokenize()
+                    .unwrap();
+            let crate::lexer::Token::Real(y) = tokens[0].0 else {
+                panic!("{rendered} did not lex as a real");
+            };
+            assert_eq!(x.to_bits(), y.to_bits(), "rendered: {rendered}");
+        }
+
+        assert_eq!(Literal::Real(1.0).to_string(), "1.0");
+        assert_eq!(Literal::Real(-0.5).to_string(), "~0.5");
+    }
 }
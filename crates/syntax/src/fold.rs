@@ -0,0 +1,330 @@
+//! Rewrites over a parsed expression tree.
+//!
+//! Negation is spelled `~` (as in SML) and parses as `Unary { Neg, .. }`;
+//! `-` is always binary subtraction, so there is no negative literal syntax.
+//! [`fold_neg_literals`] folds `~` applied to a numeric literal into a negative
+//! literal. Integers are `i64`, so the one value a literal cannot spell is
+//! `i64::MIN`, whose magnitude is out of range before negation.
+
+use crate::{
+    ast::{Arm, BinaryOp, Expr, Func, Literal, Stmt, UnaryOp, Val},
+    span::Spanned,
+};
+
+/// Fold `~<numeric literal>` into a negative literal throughout `expr`.
+pub fn fold_neg_literals(expr: Spanned<Expr>) -> Spanned<Expr> {
+    rewrite(expr, &|expr| match expr {
+        Expr::Unary { op, expr: operand } => match (op.0, &operand.0) {
+            (UnaryOp::Neg, Expr::Literal(literal)) => match negate(literal) {
+                Some(literal) => Expr::Literal(literal),
+                None => Expr::Unary { op, expr: operand },
+            },
+            _ => Expr::Unary { op, expr: operand },
+        },
+        expr => expr,
+    })
+}
+
+fn negate(literal: &Literal) -> Option<Literal> {
+    match literal {
+        Literal::Int(v) => v.checked_neg().map(Literal::Int),
+        Literal::Real(x) => Some(Literal::Real(-x)),
+        _ => None,
+    }
+}
+
+/// Evaluate unary and binary nodes whose operands are literals.
+///
+/// Anything that cannot be represented as a literal is left unfolded, such as
+/// division by zero, integer overflow or a real that overflows to infinity.
+pub fn fold_constants(expr: Spanned<Expr>) -> Spanned<Expr> {
+    rewrite(expr, &|expr| match expr {
+        Expr::Unary { op, expr: operand } => match (op.0, &operand.0) {
+            (UnaryOp::Neg, Expr::Literal(literal)) => match negate(literal) {
+                Some(literal) => Expr::Literal(literal),
+                None => Expr::Unary { op, expr: operand },
+            },
+            (UnaryOp::Not, Expr::Literal(Literal::Bool(b))) => Expr::Literal(Literal::Bool(!b)),
+            _ => Expr::Unary { op, expr: operand },
+        },
+        Expr::Binary { left, op, right } => match (&left.0, &right.0) {
+            (Expr::Literal(l), Expr::Literal(r)) => match eval_binary(op.0, l, r) {
+                Some(literal) => Expr::Literal(literal),
+                None => Expr::Binary { left, op, right },
+            },
+            _ => Expr::Binary { left, op, right },
+        },
+        expr => expr,
+    })
+}
+
+fn eval_binary(op: BinaryOp, left: &Literal, right: &Literal) -> Option<Literal> {
+    use BinaryOp::*;
+
+    let literal = match (left, right) {
+        (Literal::Int(a), Literal::Int(b)) => match op {
+            Add => Literal::Int(a.checked_add(*b)?),
+            Sub => Literal::Int(a.checked_sub(*b)?),
+            Mul => Literal::Int(a.checked_mul(*b)?),
+            Div => Literal::Int(floor_div(*a, *b)?),
+            Rem => Literal::Int(floor_mod(*a, *b)?),
+            Eq => Literal::Bool(a == b),
+            NotEq => Literal::Bool(a != b),
+            Less => Literal::Bool(a < b),
+            LessEq => Literal::Bool(a <= b),
+            Greater => Literal::Bool(a > b),
+            GreaterEq => Literal::Bool(a >= b),
+            _ => return None,
+        },
+        (Literal::Real(a), Literal::Real(b)) => match op {
+            Add => finite_real(a + b)?,
+            Sub => finite_real(a - b)?,
+            Mul => finite_real(a * b)?,
+            Div => finite_real(a / b)?,
+            Less => Literal::Bool(a < b),
+            LessEq => Literal::Bool(a <= b),
+            Greater => Literal::Bool(a > b),
+            GreaterEq => Literal::Bool(a >= b),
+            _ => return None,
+        },
+        (Literal::Bool(a), Literal::Bool(b)) => match op {
+            LogicalAnd => Literal::Bool(*a && *b),
+            LogicalOr => Literal::Bool(*a || *b),
+            Eq => Literal::Bool(a == b),
+            NotEq => Literal::Bool(a != b),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(literal)
+}
+
+/// There is no literal for infinity or NaN, so such results are not folded.
+fn finite_real(x: f64) -> Option<Literal> {
+    x.is_finite().then_some(Literal::Real(x))
+}
+
+/// `div` as in SML, rounding toward negative infinity rather than toward zero.
+fn floor_div(a: i64, b: i64) -> Option<i64> {
+    let q = a.checked_div(b)?;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        Some(q - 1)
+    } else {
+        Some(q)
+    }
+}
+
+/// `mod` as in SML, taking the sign of the divisor so that
+/// `a = b * (a div b) + a mod b`.
+fn floor_mod(a: i64, b: i64) -> Option<i64> {
+    let r = a.checked_rem(b)?;
+    if r != 0 && (r < 0) != (b < 0) {
+        Some(r + b)
+    } else {
+        Some(r)
+    }
+}
+
+/// Rebuild `expr` bottom-up, applying `f` to every node after its children.
+fn rewrite((expr, span): Spanned<Expr>, f: &impl Fn(Expr) -> Expr) -> Spanned<Expr> {
+    let expr = match expr {
+        Expr::Unary { op, expr } => Expr::Unary {
+            op,
+            expr: Box::new(rewrite(*expr, f)),
+        },
+        Expr::Borrow { op, expr } => Expr::Borrow {
+            op,
+            expr: Box::new(rewrite(*expr, f)),
+        },
+        Expr::Tuple(elems) => Expr::Tuple(elems.into_iter().map(|e| rewrite(e, f)).collect()),
+        Expr::List(elems) => Expr::List(elems.into_iter().map(|e| rewrite(e, f)).collect()),
+        Expr::Apply { callee, arg } => Expr::Apply {
+            callee: Box::new(rewrite(*callee, f)),
+            arg: Box::new(rewrite(*arg, f)),
+        },
+        Expr::Binary { left, op, right } => Expr::Binary {
+            left: Box::new(rewrite(*left, f)),
+            op,
+            right: Box::new(rewrite(*right, f)),
+        },
+        Expr::Let { stmts, expr } => Expr::Let {
+            stmts: stmts.into_iter().map(|s| rewrite_stmt(s, f)).collect(),
+            expr: Box::new(rewrite(*expr, f)),
+        },
+        Expr::Seq { stmts, expr } => Expr::Seq {
+            stmts: stmts.into_iter().map(|s| rewrite_stmt(s, f)).collect(),
+            expr: Box::new(rewrite(*expr, f)),
+        },
+        Expr::If {
+            condition,
+            then_expr,
+            else_expr,
+        } => Expr::If {
+            condition: Box::new(rewrite(*condition, f)),
+            then_expr: Box::new(rewrite(*then_expr, f)),
+            else_expr: Box::new(rewrite(*else_expr, f)),
+        },
+        Expr::Ascription { expr, ty } => Expr::Ascription {
+            expr: Box::new(rewrite(*expr, f)),
+            ty,
+        },
+        Expr::Case { scrutinee, arms } => Expr::Case {
+            scrutinee: Box::new(rewrite(*scrutinee, f)),
+            arms: arms
+                .into_iter()
+                .map(|Arm { pattern, expr }| Arm {
+                    pattern,
+                    expr: rewrite(expr, f),
+                })
+                .collect(),
+        },
+        expr @ (Expr::Literal(_) | Expr::Local(_)) => expr,
+    };
+    (f(expr), span)
+}
+
+fn rewrite_stmt((stmt, span): Spanned<Stmt>, f: &impl Fn(Expr) -> Expr) -> Spanned<Stmt> {
+    let stmt = match stmt {
+        Stmt::Val(Val { name, ty, expr }) => Stmt::Val(Val {
+            name,
+            ty,
+            expr: rewrite(expr, f),
+        }),
+        Stmt::Fun(Func {
+            name,
+            params,
+            ty,
+            expr,
+        }) => Stmt::Fun(Func {
+            name,
+            params,
+            ty,
+            expr: rewrite(expr, f),
+        }),
+        Stmt::Assign { target, value } => Stmt::Assign {
+            target,
+            value: rewrite(value, f),
+        },
+        Stmt::While { condition, body } => Stmt::While {
+            condition: rewrite(condition, f),
+            body: Box::new(rewrite_stmt(*body, f)),
+        },
+        Stmt::Expr(expr) => Stmt::Expr(rewrite(expr, f)),
+    };
+    (stmt, span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_str, span::SourceId};
+
+    fn fold(input: &str) -> Spanned<Expr> {
+        fold_neg_literals(parse_str(SourceId::default(), input).unwrap())
+    }
+
+    #[test]
+    fn test_fold_neg_real() {
+        let (expr, span) = fold("~2.5");
+        assert_eq!(expr, Expr::Literal(Literal::Real(-2.5)));
+        assert_eq!(span.range, 0..4);
+
+        let (expr, _) = fold("~~2.5");
+        assert_eq!(expr, Expr::Literal(Literal::Real(2.5)));
+
+        let (expr, _) = fold("let val x = ~1.0 in x - ~0.5 end");
+        assert_eq!(expr.to_string(), "let val x = (~1.0) in (x - (~0.5)) end");
+    }
+
+    #[test]
+    fn test_fold_neg_int() {
+        let (expr, span) = fold("~5");
+        assert_eq!(expr, Expr::Literal(Literal::Int(-5)));
+        assert_eq!(span.range, 0..2);
+        assert_eq!(expr.to_string(), "(~5)");
+
+        let (expr, _) = fold("~9223372036854775807");
+        assert_eq!(expr, Expr::Literal(Literal::Int(-i64::MAX)));
+        assert_eq!(fold("~~7").0, Expr::Literal(Literal::Int(7)));
+    }
+
+    #[test]
+    fn test_folded_literal_display_round_trips() {
+        for input in ["f (~1)", "g (~2.5) x", "(~3, [~4])", "h (~1) - ~2"] {
+            let folded = fold(input);
+            let reparsed = fold(&folded.0.to_string());
+            assert!(folded.0.structurally_eq(&reparsed.0), "{}", folded.0);
+        }
+        assert_eq!(fold("f (~1)").0.to_string(), "(f (~1))");
+    }
+
+    fn fold_const(input: &str) -> Spanned<Expr> {
+        fold_constants(parse_str(SourceId::default(), input).unwrap())
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let (expr, span) = fold_const("2 + 3 * 4");
+        assert_eq!(expr, Expr::Literal(Literal::Int(14)));
+        assert_eq!(span.range, 0..9);
+
+        assert_eq!(fold_const("1.5 * 2.0").0, Expr::Literal(Literal::Real(3.0)));
+        assert_eq!(fold_const("1 - 2").0, Expr::Literal(Literal::Int(-1)));
+        assert_eq!(fold_const("~3 * 4").0, Expr::Literal(Literal::Int(-12)));
+        assert_eq!(
+            fold_const("true && not false").0,
+            Expr::Literal(Literal::Bool(true))
+        );
+        assert_eq!(
+            fold_const("10 mod 4 < 3").0,
+            Expr::Literal(Literal::Bool(true))
+        );
+        assert_eq!(
+            fold_const("let val x = 2 * 3 in x + (1 + 1) end")
+                .0
+                .to_string(),
+            "let val x = 6 in (x + 2) end"
+        );
+    }
+
+    #[test]
+    fn test_fold_div_mod_round_down() {
+        let cases = [
+            ("7 div 2", 3),
+            ("~7 div 2", -4),
+            ("7 div ~2", -4),
+            ("~7 div ~2", 3),
+            ("~6 div 2", -3),
+            ("7 mod 2", 1),
+            ("~7 mod 2", 1),
+            ("7 mod ~2", -1),
+            ("~7 mod ~2", -1),
+            ("~6 mod 2", 0),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                fold_const(input).0,
+                Expr::Literal(Literal::Int(expected)),
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_fold_constants_leaves_undefined() {
+        assert_eq!(fold_const("1 div 0").0.to_string(), "(1 div 0)");
+        assert_eq!(fold_const("1 mod 0").0.to_string(), "(1 mod 0)");
+        assert_eq!(
+            fold_const("9223372036854775807 + 1").0.to_string(),
+            "(9223372036854775807 + 1)"
+        );
+        assert_eq!(fold_const("1 + 2.0").0.to_string(), "(1 + 2.0)");
+        assert_eq!(fold_const("x + 2 * 3").0.to_string(), "(x + 6)");
+        assert_eq!(fold_const("1.0 / 0.0").0.to_string(), "(1.0 div 0.0)");
+        assert_eq!(fold_const("1e308 * 10.0").0.to_string(), "(1e308 * 10.0)");
+        assert_eq!(
+            fold_const("1e300 / 1e-300").0.to_string(),
+            "(1e300 div 1e-300)"
+        );
+    }
+}
@@ -1,7 +1,11 @@
-use crate::span::{SourceId, Span, Spanned};
+use crate::span::{SourceDatabase, SourceId, Span, Spanned};
 use internment::Intern;
-use miette::Diagnostic;
-use std::fmt;
+use miette::{Diagnostic, GraphicalReportHandler, GraphicalTheme, Report};
+use std::{
+    fmt,
+    num::{IntErrorKind, ParseIntError},
+    ops::Range,
+};
 use thiserror::Error;
 
 #[derive(Debug, Clone, PartialEq, Error, Diagnostic)]
@@ -9,10 +13,22 @@ pub enum LexError {
     #[error("invalid integer literal: {0}")]
     #[diagnostic(
         code(lex::invalid_int),
-        help("ensure the integer is within valid range (0 to {})", usize::MAX)
+        help("ensure the integer is within valid range (0 to {})", i64::MAX)
     )]
     InvalidInt(String, #[label("here")] Span),
 
+    #[error("integer literal is too large: {literal}")]
+    #[diagnostic(
+        code(lex::int_overflow),
+        help("integer literals must not exceed i64::MAX ({max})")
+    )]
+    IntLiteralOverflow {
+        literal: String,
+        max: i64,
+        #[label("here")]
+        span: Span,
+    },
+
     #[error("invalid float literal: {0}")]
     #[diagnostic(
         code(lex::invalid_float),
@@ -20,6 +36,17 @@ pub enum LexError {
     )]
     InvalidFloat(String, #[label("here")] Span),
 
+    #[error("real literal is too large: {literal}")]
+    #[diagnostic(
+        code(lex::float_overflow),
+        help("real literals must not exceed f64::MAX ({:e})", f64::MAX)
+    )]
+    FloatOverflow {
+        literal: String,
+        #[label("here")]
+        span: Span,
+    },
+
     #[error("character literal cannot be empty")]
     #[diagnostic(
         code(lex::empty_char),
@@ -41,6 +68,19 @@ pub enum LexError {
     )]
     UnknownEscape(char, #[label("here")] Span),
 
+    #[error("invalid unicode escape: \\u{{{value:X}}}")]
+    #[diagnostic(
+        code(lex::invalid_unicode_escape),
+        help(
+            "the value must be a unicode scalar value: at most 10FFFF and not a surrogate (D800-DFFF)"
+        )
+    )]
+    InvalidUnicodeEscape {
+        value: u32,
+        #[label("here")]
+        span: Span,
+    },
+
     #[error("unterminated character literal")]
     #[diagnostic(
         code(lex::unterminated_char),
@@ -55,6 +95,34 @@ pub enum LexError {
     )]
     InvalidNumberChar(#[label("here")] Span),
 
+    #[error("misplaced digit separator in number literal")]
+    #[diagnostic(
+        code(lex::invalid_digit_separator),
+        help("underscores may only appear between two digits, e.g., 1_000_000")
+    )]
+    InvalidDigitSeparator(#[label("here")] Span),
+
+    #[error("radix literal has no digits")]
+    #[diagnostic(
+        code(lex::empty_radix_literal),
+        help("add at least one digit after the prefix, e.g., 0xFF, 0o17, 0b1010")
+    )]
+    EmptyRadixLiteral(#[label("here")] Span),
+
+    #[error("unterminated block comment")]
+    #[diagnostic(
+        code(lex::unterminated_comment),
+        help("block comments must be closed with a matching *)")
+    )]
+    UnterminatedComment(#[label("comment opened here")] Span),
+
+    #[error("unexpected character '{0}'")]
+    #[diagnostic(
+        code(lex::unexpected_char),
+        help("this character is not part of the language; remove it")
+    )]
+    UnexpectedChar(char, #[label("here")] Span),
+
     #[error("invalid token")]
     #[diagnostic(
         code(lex::invalid_token),
@@ -63,7 +131,110 @@ pub enum LexError {
     InvalidToken(#[label("here")] Span),
 }
 
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+impl LexError {
+    /// Where in the source the error was found.
+    pub fn span(&self) -> &Span {
+        match self {
+            LexError::InvalidInt(_, span)
+            | LexError::InvalidFloat(_, span)
+            | LexError::EmptyChar(span)
+            | LexError::MultiChar(span)
+            | LexError::UnknownEscape(_, span)
+            | LexError::UnterminatedChar(span)
+            | LexError::InvalidNumberChar(span)
+            | LexError::InvalidDigitSeparator(span)
+            | LexError::EmptyRadixLiteral(span)
+            | LexError::UnterminatedComment(span)
+            | LexError::UnexpectedChar(_, span)
+            | LexError::InvalidToken(span) => span,
+            LexError::IntLiteralOverflow { span, .. }
+            | LexError::FloatOverflow { span, .. }
+            | LexError::InvalidUnicodeEscape { span, .. } => span,
+        }
+    }
+}
+
+/// Render every error in `errors` against the source text of `src_id`.
+pub fn report_lex_errors(db: &SourceDatabase, src_id: SourceId, errors: &[LexError]) -> String {
+    let handler = GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor());
+    let mut out = String::new();
+    for error in errors {
+        let report = Report::new(error.clone()).with_source_code(db.named_source(src_id));
+        // Writing into a `String` cannot fail.
+        let _ = handler.render_report(&mut out, report.as_ref());
+    }
+    out
+}
+
+/// Render one token per line next to the source text it covers, e.g.
+/// `Int(42) @ 0..2 "42"`.
+pub fn debug_tokens(src: &str, tokens: &[Spanned<Token>]) -> String {
+    use std::fmt::Write;
+
+    let mut out = String::new();
+    for (token, span) in tokens {
+        let text = src.get(span.range.clone()).unwrap_or("<out of bounds>");
+        // Writing into a `String` cannot fail.
+        let _ = writeln!(out, "{token:?} @ {:?} {text:?}", span.range);
+    }
+    out
+}
+
+/// The reserved words of every dialect and the tokens they lex to. The word
+/// operators that [`LexerConfig`] can turn off are in [`ARITHMETIC_WORDS`] and
+/// [`LOGICAL_WORDS`].
+pub const KEYWORDS: &[(&str, Token)] = &[
+    ("fun", Token::KwFun),
+    ("int", Token::KwInt),
+    ("bool", Token::KwBool),
+    ("real", Token::KwReal),
+    ("char", Token::KwChar),
+    ("unit", Token::KwUnit),
+    ("val", Token::KwVal),
+    ("let", Token::KwLet),
+    ("in", Token::KwIn),
+    ("end", Token::KwEnd),
+    ("if", Token::KwIf),
+    ("then", Token::KwThen),
+    ("else", Token::KwElse),
+    ("not", Token::KwNot),
+    ("mut", Token::KwMut),
+    ("while", Token::KwWhile),
+    ("do", Token::KwDo),
+    ("and", Token::KwAnd),
+    ("case", Token::KwCase),
+    ("of", Token::KwOf),
+];
+
+/// `div` and `mod`, reserved when [`LexerConfig::arithmetic_words`] is set.
+pub const ARITHMETIC_WORDS: &[(&str, Token)] = &[("div", Token::KwDiv), ("mod", Token::KwMod)];
+
+/// SML's `andalso` and `orelse`, reserved as spellings of `&&` and `||` when
+/// [`LexerConfig::logical_words`] is set.
+pub const LOGICAL_WORDS: &[(&str, Token)] = &[("andalso", Token::AndAnd), ("orelse", Token::Or)];
+
+/// Write `c` as a quoted char literal the lexer reads back as `c`, escaping
+/// quotes, backslashes and control characters.
+pub(crate) fn write_char_literal(f: &mut fmt::Formatter<'_>, c: char) -> fmt::Result {
+    match c {
+        '\'' => f.write_str("'\\''"),
+        '\\' => f.write_str("'\\\\'"),
+        '\n' => f.write_str("'\\n'"),
+        '\r' => f.write_str("'\\r'"),
+        '\t' => f.write_str("'\\t'"),
+        '\0' => f.write_str("'\\0'"),
+        c if c.is_control() => write!(f, "'\\u{{{:x}}}'", c as u32),
+        c => write!(f, "'{c}'"),
+    }
+}
+
+/// Whether `s` is a reserved word under the default [`LexerConfig`]. Use
+/// [`LexerConfig::is_keyword`] for other dialects.
+pub fn is_keyword_str(s: &str) -> bool {
+    LexerConfig::default().is_keyword(s)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub enum Token {
     // Keywords
     KwFun,
@@ -85,14 +256,21 @@ pub enum Token {
     KwDo,
     KwMod,
     KwDiv,
+    KwAnd,
+    KwCase,
+    KwOf,
     Comma,
-    Cons, // ::
+    Semi,
+    Underscore, // the `_` wildcard
+    Cons,       // ::
     Eq,
     NotEq,
     Colon,
     ColonEq,
     LParen,
     RParen,
+    LBracket,
+    RBracket,
     Gt,
     GtEq,
     Less,
@@ -103,15 +281,152 @@ pub enum Token {
     Tilde,
     Plus,
     Minus,
+    Arrow,    // ->
+    FatArrow, // =>
+    Bar,      // | between case arms
     Star,
+    Slash,
+    Percent,
+    Caret,
     Real(f64),
-    Int(usize),
+    Int(i64),
     Bool(bool),
     Char(char),
     Ident(Intern<String>),
+    TypeVar(Intern<String>), // 'a, stored without the quote
+    Eof,
+}
+
+/// The kind of a [`Token`], without any payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    KwFun,
+    KwInt,
+    KwBool,
+    KwReal,
+    KwChar,
+    KwUnit,
+    KwVal,
+    KwLet,
+    KwIn,
+    KwEnd,
+    KwIf,
+    KwThen,
+    KwElse,
+    KwNot,
+    KwMut,
+    KwWhile,
+    KwDo,
+    KwMod,
+    KwDiv,
+    KwAnd,
+    KwCase,
+    KwOf,
+    Comma,
+    Semi,
+    Underscore,
+    Cons,
+    Eq,
+    NotEq,
+    Colon,
+    ColonEq,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Gt,
+    GtEq,
+    Less,
+    LessEq,
+    AndAnd,
+    Or,
+    And,
+    Tilde,
+    Plus,
+    Minus,
+    Arrow,
+    FatArrow,
+    Bar,
+    Star,
+    Slash,
+    Percent,
+    Caret,
+    Real,
+    Int,
+    Bool,
+    Char,
+    Ident,
+    TypeVar,
     Eof,
 }
 
+/// Names the kind the way a diagnostic would, e.g. `)` or `integer literal`.
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TokenKind::KwFun => "fun",
+            TokenKind::KwInt => "int",
+            TokenKind::KwBool => "bool",
+            TokenKind::KwReal => "real",
+            TokenKind::KwChar => "char",
+            TokenKind::KwUnit => "unit",
+            TokenKind::KwVal => "val",
+            TokenKind::KwLet => "let",
+            TokenKind::KwIn => "in",
+            TokenKind::KwEnd => "end",
+            TokenKind::KwIf => "if",
+            TokenKind::KwThen => "then",
+            TokenKind::KwElse => "else",
+            TokenKind::KwNot => "not",
+            TokenKind::KwMut => "mut",
+            TokenKind::KwWhile => "while",
+            TokenKind::KwDo => "do",
+            TokenKind::KwMod => "mod",
+            TokenKind::KwDiv => "div",
+            TokenKind::KwAnd => "and",
+            TokenKind::KwCase => "case",
+            TokenKind::KwOf => "of",
+            TokenKind::Comma => ",",
+            TokenKind::Semi => ";",
+            TokenKind::Underscore => "_",
+            TokenKind::Cons => "::",
+            TokenKind::Eq => "=",
+            TokenKind::NotEq => "<>",
+            TokenKind::Colon => ":",
+            TokenKind::ColonEq => ":=",
+            TokenKind::LParen => "(",
+            TokenKind::RParen => ")",
+            TokenKind::LBracket => "[",
+            TokenKind::RBracket => "]",
+            TokenKind::Gt => ">",
+            TokenKind::GtEq => ">=",
+            TokenKind::Less => "<",
+            TokenKind::LessEq => "<=",
+            TokenKind::AndAnd => "&&",
+            TokenKind::Or => "||",
+            TokenKind::And => "&",
+            TokenKind::Tilde => "~",
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Arrow => "->",
+            TokenKind::FatArrow => "=>",
+            TokenKind::Bar => "|",
+            TokenKind::Star => "*",
+            TokenKind::Slash => "/",
+            TokenKind::Percent => "%",
+            TokenKind::Caret => "^",
+            TokenKind::Real => "real literal",
+            TokenKind::Int => "integer literal",
+            TokenKind::Bool => "boolean literal",
+            TokenKind::Char => "character literal",
+            TokenKind::Ident => "identifier",
+            TokenKind::TypeVar => "type variable",
+            TokenKind::Eof => "end of input",
+        };
+        f.write_str(s)
+    }
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -134,7 +449,12 @@ impl fmt::Display for Token {
             Token::KwDo => write!(f, "do"),
             Token::KwMod => write!(f, "mod"),
             Token::KwDiv => write!(f, "div"),
+            Token::KwAnd => write!(f, "and"),
+            Token::KwCase => write!(f, "case"),
+            Token::KwOf => write!(f, "of"),
             Token::Comma => write!(f, ","),
+            Token::Semi => write!(f, ";"),
+            Token::Underscore => write!(f, "_"),
             Token::Cons => write!(f, "::"),
             Token::Eq => write!(f, "="),
             Token::NotEq => write!(f, "<>"),
@@ -142,46 +462,268 @@ impl fmt::Display for Token {
             Token::ColonEq => write!(f, ":="),
             Token::LParen => write!(f, "("),
             Token::RParen => write!(f, ")"),
+            Token::LBracket => write!(f, "["),
+            Token::RBracket => write!(f, "]"),
             Token::Gt => write!(f, ">"),
             Token::GtEq => write!(f, ">="),
             Token::Less => write!(f, "<"),
             Token::LessEq => write!(f, "<="),
             Token::AndAnd => write!(f, "&&"),
-            Token::Or => write!(f, "or"),
-            Token::And => write!(f, "and"),
+            Token::Or => write!(f, "||"),
+            Token::And => write!(f, "&"),
             Token::Tilde => write!(f, "~"),
             Token::Plus => write!(f, "+"),
             Token::Minus => write!(f, "-"),
+            Token::Arrow => write!(f, "->"),
+            Token::FatArrow => write!(f, "=>"),
+            Token::Bar => write!(f, "|"),
             Token::Star => write!(f, "*"),
-            Token::Real(v) => write!(f, "{v}"),
+            Token::Slash => write!(f, "/"),
+            Token::Percent => write!(f, "%"),
+            Token::Caret => write!(f, "^"),
+            Token::Real(v) => write!(f, "{v:?}"),
             Token::Int(v) => write!(f, "{v}"),
             Token::Bool(v) => write!(f, "{v}"),
-            Token::Char(c) => write!(f, "'{c}'"),
+            Token::Char(c) => write_char_literal(f, *c),
             Token::Ident(id) => write!(f, "{id}"),
+            Token::TypeVar(name) => write!(f, "'{name}"),
             Token::Eof => write!(f, ""),
         }
     }
 }
 
+impl Token {
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            Token::KwFun => TokenKind::KwFun,
+            Token::KwInt => TokenKind::KwInt,
+            Token::KwBool => TokenKind::KwBool,
+            Token::KwReal => TokenKind::KwReal,
+            Token::KwChar => TokenKind::KwChar,
+            Token::KwUnit => TokenKind::KwUnit,
+            Token::KwVal => TokenKind::KwVal,
+            Token::KwLet => TokenKind::KwLet,
+            Token::KwIn => TokenKind::KwIn,
+            Token::KwEnd => TokenKind::KwEnd,
+            Token::KwIf => TokenKind::KwIf,
+            Token::KwThen => TokenKind::KwThen,
+            Token::KwElse => TokenKind::KwElse,
+            Token::KwNot => TokenKind::KwNot,
+            Token::KwMut => TokenKind::KwMut,
+            Token::KwWhile => TokenKind::KwWhile,
+            Token::KwDo => TokenKind::KwDo,
+            Token::KwMod => TokenKind::KwMod,
+            Token::KwDiv => TokenKind::KwDiv,
+            Token::KwAnd => TokenKind::KwAnd,
+            Token::KwCase => TokenKind::KwCase,
+            Token::KwOf => TokenKind::KwOf,
+            Token::Comma => TokenKind::Comma,
+            Token::Semi => TokenKind::Semi,
+            Token::Underscore => TokenKind::Underscore,
+            Token::Cons => TokenKind::Cons,
+            Token::Eq => TokenKind::Eq,
+            Token::NotEq => TokenKind::NotEq,
+            Token::Colon => TokenKind::Colon,
+            Token::ColonEq => TokenKind::ColonEq,
+            Token::LParen => TokenKind::LParen,
+            Token::RParen => TokenKind::RParen,
+            Token::LBracket => TokenKind::LBracket,
+            Token::RBracket => TokenKind::RBracket,
+            Token::Gt => TokenKind::Gt,
+            Token::GtEq => TokenKind::GtEq,
+            Token::Less => TokenKind::Less,
+            Token::LessEq => TokenKind::LessEq,
+            Token::AndAnd => TokenKind::AndAnd,
+            Token::Or => TokenKind::Or,
+            Token::And => TokenKind::And,
+            Token::Tilde => TokenKind::Tilde,
+            Token::Plus => TokenKind::Plus,
+            Token::Minus => TokenKind::Minus,
+            Token::Arrow => TokenKind::Arrow,
+            Token::FatArrow => TokenKind::FatArrow,
+            Token::Bar => TokenKind::Bar,
+            Token::Star => TokenKind::Star,
+            Token::Slash => TokenKind::Slash,
+            Token::Percent => TokenKind::Percent,
+            Token::Caret => TokenKind::Caret,
+            Token::Real(_) => TokenKind::Real,
+            Token::Int(_) => TokenKind::Int,
+            Token::Bool(_) => TokenKind::Bool,
+            Token::Char(_) => TokenKind::Char,
+            Token::Ident(_) => TokenKind::Ident,
+            Token::TypeVar(_) => TokenKind::TypeVar,
+            Token::Eof => TokenKind::Eof,
+        }
+    }
+
+    /// Number of characters in the token's rendered form, e.g. 2 for `::`.
+    pub fn display_width(&self) -> usize {
+        self.to_string().chars().count()
+    }
+
+    /// Reserved words, including the word operators `not`, `div` and `mod`.
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            Token::KwFun
+                | Token::KwInt
+                | Token::KwBool
+                | Token::KwReal
+                | Token::KwChar
+                | Token::KwUnit
+                | Token::KwVal
+                | Token::KwLet
+                | Token::KwIn
+                | Token::KwEnd
+                | Token::KwIf
+                | Token::KwThen
+                | Token::KwElse
+                | Token::KwNot
+                | Token::KwMut
+                | Token::KwWhile
+                | Token::KwDo
+                | Token::KwMod
+                | Token::KwDiv
+                | Token::KwAnd
+                | Token::KwCase
+                | Token::KwOf
+        )
+    }
+
+    /// Symbolic operators. Delimiters such as `(`, `,` and `:` are not operators.
+    pub fn is_operator(&self) -> bool {
+        matches!(
+            self,
+            Token::Cons
+                | Token::Eq
+                | Token::NotEq
+                | Token::ColonEq
+                | Token::Gt
+                | Token::GtEq
+                | Token::Less
+                | Token::LessEq
+                | Token::AndAnd
+                | Token::Or
+                | Token::And
+                | Token::Tilde
+                | Token::Plus
+                | Token::Minus
+                | Token::Arrow
+                | Token::FatArrow
+                | Token::Star
+                | Token::Slash
+                | Token::Percent
+                | Token::Caret
+        )
+    }
+
+    pub fn is_literal(&self) -> bool {
+        matches!(
+            self,
+            Token::Real(_) | Token::Int(_) | Token::Bool(_) | Token::Char(_)
+        )
+    }
+}
+
+/// Which optional spellings the lexer recognizes, so front-ends for different
+/// ML dialects can share it.
+///
+/// A disabled word lexes as a plain identifier. A disabled `&&` or `||` lexes
+/// as two `&` or `|` tokens, and a disabled `/` or `%` is an unexpected
+/// character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LexerConfig {
+    /// Lex `andalso` and `orelse` as `&&` and `||`.
+    pub logical_words: bool,
+    /// Lex `&&` and `||` as logical operators.
+    pub logical_symbols: bool,
+    /// Lex `div` and `mod` as division and remainder.
+    pub arithmetic_words: bool,
+    /// Lex `/` and `%` as division and remainder.
+    pub arithmetic_symbols: bool,
+}
+
+/// Every spelling enabled.
+impl Default for LexerConfig {
+    fn default() -> Self {
+        Self {
+            logical_words: true,
+            logical_symbols: true,
+            arithmetic_words: true,
+            arithmetic_symbols: true,
+        }
+    }
+}
+
+impl LexerConfig {
+    /// The token `word` lexes to if it is reserved under this config.
+    pub fn keyword(&self, word: &str) -> Option<Token> {
+        let optional = [
+            (self.arithmetic_words, ARITHMETIC_WORDS),
+            (self.logical_words, LOGICAL_WORDS),
+        ];
+        let enabled = optional
+            .into_iter()
+            .filter(|(on, _)| *on)
+            .flat_map(|(_, words)| words);
+        KEYWORDS
+            .iter()
+            .chain(enabled)
+            .find(|(keyword, _)| *keyword == word)
+            .map(|(_, token)| *token)
+    }
+
+    /// Whether `word` is reserved under this config and so cannot be used as
+    /// a name.
+    pub fn is_keyword(&self, word: &str) -> bool {
+        self.keyword(word).is_some()
+    }
+}
+
+/// A single text replacement: the bytes in `range` of the old source were
+/// replaced by `new_len` bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub new_len: usize,
+}
+
+impl Edit {
+    /// Where `offset`, at or after the end of the edited range in the old
+    /// source, lands in the new source.
+    fn shift(&self, offset: usize) -> usize {
+        offset - self.range.end + self.range.start + self.new_len
+    }
+}
+
 pub struct Lexer<'src> {
     src_id: SourceId,
-    chars: std::iter::Peekable<std::str::CharIndices<'src>>,
     source: &'src str,
     current_pos: usize,
+    finished: bool,
+    config: LexerConfig,
 }
 
 impl<'src> Lexer<'src> {
-    pub fn new(src_id: SourceId, input: &'src str) -> Self {
+    /// Lex `input` with the spellings enabled in `config`.
+    pub fn new(src_id: SourceId, input: &'src str, config: LexerConfig) -> Self {
         Self {
             src_id,
-            chars: input.char_indices().peekable(),
             source: input,
             current_pos: 0,
+            finished: false,
+            config,
         }
     }
 
+    /// The exact source text covered by `span`, e.g. `1_000` for a `Token::Int(1000)`.
+    pub fn lexeme(&self, span: &Span) -> &'src str {
+        debug_assert_eq!(span.src, self.src_id, "span belongs to another source");
+        &self.source[span.range.clone()]
+    }
+
     fn next_char(&mut self) -> Option<(usize, char)> {
-        let result = self.chars.next();
+        let result = self.peek();
         if let Some((pos, c)) = result {
             self.current_pos = pos + c.len_utf8();
         }
@@ -189,106 +731,106 @@ impl<'src> Lexer<'src> {
     }
 
     fn peek(&mut self) -> Option<(usize, char)> {
-        self.chars.peek().copied()
+        self.peek_char().map(|c| (self.current_pos, c))
     }
 
     fn peek_char(&mut self) -> Option<char> {
-        self.chars.peek().map(|(_, c)| *c)
+        let byte = *self.source.as_bytes().get(self.current_pos)?;
+        if byte.is_ascii() {
+            Some(byte as char)
+        } else {
+            self.source[self.current_pos..].chars().next()
+        }
+    }
+
+    /// Skip the longest run of ASCII bytes matching `pred`, without decoding
+    /// them as characters.
+    fn skip_ascii_while(&mut self, pred: impl Fn(u8) -> bool) {
+        let rest = &self.source.as_bytes()[self.current_pos..];
+        self.current_pos += rest.iter().position(|&b| !pred(b)).unwrap_or(rest.len());
+    }
+
+    /// Look `n` characters past the current one without consuming anything.
+    ///
+    /// Reads straight from the source slice rather than a lookahead buffer:
+    /// callers look at most two characters ahead, so the re-scan is constant
+    /// time and allocation-free, and there is no buffer to keep in sync when
+    /// [`Lexer::skip_ascii_while`] moves `current_pos` directly.
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        let (pos, _) = self.peek()?;
+        self.source[pos..].chars().nth(n)
     }
 
     fn skip_whitespace(&mut self) {
-        while let Some((_, c)) = self.peek() {
-            if c.is_whitespace() {
-                self.next_char();
-            } else {
-                break;
+        loop {
+            self.skip_ascii_while(|b| b.is_ascii_whitespace());
+            // Vertical tab and non-ASCII spaces are rare enough to take one at a time.
+            match self.peek_char() {
+                Some(c) if c.is_whitespace() => {
+                    self.next_char();
+                }
+                _ => break,
             }
         }
     }
 
-    pub fn tokenize(mut self) -> Result<Vec<Spanned<Token>>, Vec<LexError>> {
-        let mut tokens = Vec::new();
-        let mut errors = Vec::new();
-
+    /// Skip whitespace and (possibly nested) `(* ... *)` block comments.
+    fn skip_trivia(&mut self) -> Result<(), LexError> {
         loop {
             self.skip_whitespace();
+            match self.peek() {
+                Some((start, '(')) if self.peek_nth(1) == Some('*') => {
+                    self.skip_block_comment(start)?;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
 
-            let Some((start, c)) = self.peek() else {
-                break;
-            };
+    fn skip_block_comment(&mut self, start: usize) -> Result<(), LexError> {
+        self.next_char(); // consume '('
+        self.next_char(); // consume '*'
+        let mut depth = 1usize;
 
-            let result = match c {
-                ',' => {
-                    self.next_char();
-                    Ok(Token::Comma)
-                }
-                '~' => {
-                    self.next_char();
-                    Ok(Token::Tilde)
-                }
-                '(' => {
-                    self.next_char();
-                    Ok(Token::LParen)
-                }
-                ')' => {
-                    self.next_char();
-                    Ok(Token::RParen)
-                }
-                '=' => {
-                    self.next_char();
-                    Ok(Token::Eq)
-                }
-                '+' => {
-                    self.next_char();
-                    Ok(Token::Plus)
-                }
-                '-' => {
+        while let Some((_, c)) = self.next_char() {
+            match c {
+                '(' if self.peek_char() == Some('*') => {
                     self.next_char();
-                    Ok(Token::Minus)
+                    depth += 1;
                 }
-                '*' => {
+                '*' if self.peek_char() == Some(')') => {
                     self.next_char();
-                    Ok(Token::Star)
-                }
-                ':' => self.lex_colon(),
-                '<' => self.lex_less(),
-                '>' => self.lex_gt(),
-                '&' => self.lex_and(),
-                '|' => self.lex_or(),
-                '\'' => self.lex_char_literal(start),
-                '0'..='9' => self.lex_number(start),
-                '.' => {
-                    // Check if this is a float starting with a dot
-                    if matches!(self.chars.clone().nth(1), Some((_, '0'..='9'))) {
-                        self.lex_number(start)
-                    } else {
-                        self.next_char();
-                        Err(Span::new(self.src_id, start..self.current_pos))
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
                     }
                 }
-                'a'..='z' | 'A'..='Z' | '_' => self.lex_ident(),
-                _ => {
-                    self.next_char();
-                    Err(Span::new(self.src_id, start..self.current_pos))
-                }
-            };
+                _ => {}
+            }
+        }
 
-            let end = self.current_pos;
-            let span = Span::new(self.src_id, start..end);
+        Err(LexError::UnterminatedComment(Span::from_len(
+            self.src_id,
+            start,
+            2,
+        )))
+    }
+
+    /// Lex the whole source, returning every error if there are any.
+    ///
+    /// The source is scanned left to right and each error lies within the
+    /// token being lexed, so errors come out already in source order.
+    pub fn tokenize(self) -> Result<Vec<Spanned<Token>>, Vec<LexError>> {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
 
+        for result in self {
             match result {
-                Ok(token) => tokens.push((token, span)),
-                Err(error_span) => {
-                    // If lex functions return Span, convert to LexError with span
-                    errors.push(LexError::InvalidToken(error_span));
-                }
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
             }
         }
 
-        // Add EOF token at the end
-        let eof_pos = self.source.len();
-        tokens.push((Token::Eof, Span::new(self.src_id, eof_pos..eof_pos)));
-
         if errors.is_empty() {
             Ok(tokens)
         } else {
@@ -296,6 +838,190 @@ impl<'src> Lexer<'src> {
         }
     }
 
+    /// Update `old_tokens` for `edit` without re-lexing all of `new_src`.
+    ///
+    /// Lexing restarts one token before the edit, since the edit may extend
+    /// or split that token, and stops at the first token past the edit that
+    /// starts where an old token did; from there on the text is unchanged,
+    /// so the old tokens are reused with their spans shifted.
+    ///
+    /// Returns the tokens alongside the errors found in the re-lexed region,
+    /// in source order as in [`Lexer::tokenize`]. Errors outside that region are
+    /// unchanged by the edit, so callers keep whatever they had for them.
+    ///
+    /// `self` supplies the source id and config; its own source is not read.
+    pub fn relex_range(
+        &self,
+        old_tokens: &[Spanned<Token>],
+        edit: Edit,
+        new_src: &str,
+    ) -> (Vec<Spanned<Token>>, Vec<LexError>) {
+        let touched = old_tokens.partition_point(|(_, span)| span.range.end < edit.range.start);
+        let keep = touched.saturating_sub(1);
+        let restart = match touched {
+            0 => 0,
+            _ => old_tokens[keep].1.range.start,
+        };
+        let after = old_tokens.partition_point(|(_, span)| span.range.start < edit.range.end);
+        let edit_end = edit.range.start + edit.new_len;
+
+        let mut tokens = old_tokens[..keep].to_vec();
+        let mut errors = Vec::new();
+        let mut old = old_tokens[after..].iter().peekable();
+        let mut lexer = Lexer {
+            src_id: self.src_id,
+            source: new_src,
+            current_pos: restart,
+            finished: false,
+            config: self.config,
+        };
+
+        while let Some(result) = lexer.next_token() {
+            let (token, span) = match result {
+                Ok(token) => token,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+            if span.range.start >= edit_end {
+                while old
+                    .next_if(|(_, s)| edit.shift(s.range.start) < span.range.start)
+                    .is_some()
+                {}
+                if old
+                    .peek()
+                    .is_some_and(|(_, s)| edit.shift(s.range.start) == span.range.start)
+                {
+                    tokens.extend(old.map(|(token, s)| {
+                        let range = edit.shift(s.range.start)..edit.shift(s.range.end);
+                        (*token, Span::new(self.src_id, range))
+                    }));
+                    break;
+                }
+            }
+            tokens.push((token, span));
+        }
+        (tokens, errors)
+    }
+
+    /// Lex the next token, or `None` once the final `Eof` has been produced.
+    fn next_token(&mut self) -> Option<Result<Spanned<Token>, LexError>> {
+        if self.finished {
+            return None;
+        }
+
+        if let Err(error) = self.skip_trivia() {
+            return Some(Err(error));
+        }
+
+        let Some((start, c)) = self.peek() else {
+            self.finished = true;
+            let eof = Span::point(self.src_id, self.source.len());
+            return Some(Ok((Token::Eof, eof)));
+        };
+
+        let result = match c {
+            ',' => {
+                self.next_char();
+                Ok(Token::Comma)
+            }
+            ';' => {
+                self.next_char();
+                Ok(Token::Semi)
+            }
+            '~' => {
+                self.next_char();
+                Ok(Token::Tilde)
+            }
+            '(' => {
+                self.next_char();
+                Ok(Token::LParen)
+            }
+            ')' => {
+                self.next_char();
+                Ok(Token::RParen)
+            }
+            '[' => {
+                self.next_char();
+                Ok(Token::LBracket)
+            }
+            ']' => {
+                self.next_char();
+                Ok(Token::RBracket)
+            }
+            '=' => {
+                self.next_char();
+                if self.peek_char() == Some('>') {
+                    self.next_char();
+                    Ok(Token::FatArrow)
+                } else {
+                    Ok(Token::Eq)
+                }
+            }
+            '+' => {
+                self.next_char();
+                Ok(Token::Plus)
+            }
+            '-' => {
+                self.next_char();
+                if self.peek_char() == Some('>') {
+                    self.next_char();
+                    Ok(Token::Arrow)
+                } else {
+                    Ok(Token::Minus)
+                }
+            }
+            '*' => {
+                self.next_char();
+                Ok(Token::Star)
+            }
+            '/' if self.config.arithmetic_symbols => {
+                self.next_char();
+                Ok(Token::Slash)
+            }
+            '%' if self.config.arithmetic_symbols => {
+                self.next_char();
+                Ok(Token::Percent)
+            }
+            '^' => {
+                self.next_char();
+                Ok(Token::Caret)
+            }
+            ':' => self.lex_colon().map_err(LexError::InvalidToken),
+            '<' => self.lex_less().map_err(LexError::InvalidToken),
+            '>' => self.lex_gt().map_err(LexError::InvalidToken),
+            '&' => Ok(self.lex_and()),
+            '|' => Ok(self.lex_or()),
+            '\'' => self.lex_quote(start),
+            '0'..='9' => self.lex_number(start),
+            '.' => {
+                // Check if this is a float starting with a dot
+                if matches!(self.peek_nth(1), Some('0'..='9')) {
+                    self.lex_number(start)
+                } else {
+                    self.next_char();
+                    Err(LexError::UnexpectedChar(
+                        c,
+                        Span::new(self.src_id, start..self.current_pos),
+                    ))
+                }
+            }
+            'a'..='z' | 'A'..='Z' | '_' => self.lex_ident().map_err(LexError::InvalidToken),
+            _ => {
+                self.next_char();
+                Err(LexError::UnexpectedChar(
+                    c,
+                    Span::new(self.src_id, start..self.current_pos),
+                ))
+            }
+        };
+
+        let end = self.current_pos;
+        let span = Span::new(self.src_id, start..end);
+        Some(result.map(|token| (token, span)))
+    }
+
     fn lex_colon(&mut self) -> Result<Token, Span> {
         self.next_char(); // consume ':'
         match self.peek_char() {
@@ -336,116 +1062,301 @@ impl<'src> Lexer<'src> {
         }
     }
 
-    fn lex_and(&mut self) -> Result<Token, Span> {
+    /// A lone `&` is the borrow operator in `&x` and `&mut x`. The longest
+    /// match wins, so `&&x` is `&&` followed by `x`; write `& &x` to borrow twice.
+    fn lex_and(&mut self) -> Token {
         self.next_char(); // consume '&'
-        if self.peek_char() == Some('&') {
+        if self.config.logical_symbols && self.peek_char() == Some('&') {
             self.next_char(); // consume second '&'
-            Ok(Token::AndAnd)
+            Token::AndAnd
         } else {
-            Ok(Token::And)
+            Token::And
         }
     }
 
-    fn lex_or(&mut self) -> Result<Token, Span> {
-        let start = self.current_pos;
+    /// `||` is logical or, and a lone `|` separates the arms of a `case`.
+    fn lex_or(&mut self) -> Token {
         self.next_char(); // consume '|'
-        if self.peek_char() == Some('|') {
+        if self.config.logical_symbols && self.peek_char() == Some('|') {
             self.next_char(); // consume second '|'
-            Ok(Token::Or)
+            Token::Or
         } else {
-            Err(Span::new(self.src_id, start..self.current_pos))
+            Token::Bar
         }
     }
 
-    fn lex_char_literal(&mut self, start: usize) -> Result<Token, Span> {
+    fn invalid_token(&self, start: usize) -> LexError {
+        LexError::InvalidToken(Span::new(self.src_id, start..self.current_pos))
+    }
+
+    /// A quote starts a type variable like `'a` when a letter follows that is
+    /// not itself closed by a quote, and a char literal like `'a'` otherwise.
+    fn lex_quote(&mut self, start: usize) -> Result<Token, LexError> {
+        let is_type_var = self
+            .peek_nth(1)
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+            && self.peek_nth(2) != Some('\'');
+        if !is_type_var {
+            return self.lex_char_literal(start);
+        }
+
+        self.next_char(); // consume '
+        let name_start = self.current_pos;
+        loop {
+            self.skip_ascii_while(|b| b.is_ascii_alphanumeric() || b == b'_');
+            match self.peek_char() {
+                Some(c) if c.is_alphanumeric() => {
+                    self.next_char();
+                }
+                _ => break,
+            }
+        }
+        let name = &self.source[name_start..self.current_pos];
+        Ok(Token::TypeVar(Intern::from_ref(name)))
+    }
+
+    fn lex_char_literal(&mut self, start: usize) -> Result<Token, LexError> {
         self.next_char(); // consume opening '
 
         let Some((_, c)) = self.next_char() else {
-            return Err(Span::new(self.src_id, start..self.current_pos));
+            return Err(self.invalid_token(start));
         };
 
         let result = if c == '\\' {
             // Handle escape sequence
             let Some((_, esc)) = self.next_char() else {
-                return Err(Span::new(self.src_id, start..self.current_pos));
+                return Err(self.invalid_token(start));
             };
             match esc {
-                '\'' => '\'',
-                '\"' => '\"',
-                '\\' => '\\',
-                'n' => '\n',
-                'r' => '\r',
-                't' => '\t',
-                '0' => '\0',
+                '\'' => Ok('\''),
+                '\"' => Ok('\"'),
+                '\\' => Ok('\\'),
+                'n' => Ok('\n'),
+                'r' => Ok('\r'),
+                't' => Ok('\t'),
+                '0' => Ok('\0'),
+                'x' => self.lex_hex_escape(start),
+                'u' => self.lex_unicode_escape(start),
                 _ => {
-                    return Err(Span::new(self.src_id, start..self.current_pos));
+                    return Err(self.invalid_token(start));
                 }
             }
         } else if c == '\'' {
-            return Err(Span::new(self.src_id, start..self.current_pos));
+            return Err(self.invalid_token(start));
         } else {
-            c
+            Ok(c)
         };
 
         match self.next_char() {
-            Some((_, '\'')) => Ok(Token::Char(result)),
-            Some(_) => Err(Span::new(self.src_id, start..self.current_pos)),
-            None => Err(Span::new(self.src_id, start..self.current_pos)),
+            Some((_, '\'')) => result.map(Token::Char),
+            Some(_) => Err(self.invalid_token(start)),
+            None => Err(self.invalid_token(start)),
         }
     }
 
-    fn lex_number(&mut self, start: usize) -> Result<Token, Span> {
+    /// Lex the two hex digits of a `\xFF` escape.
+    fn lex_hex_escape(&mut self, start: usize) -> Result<char, LexError> {
+        let mut value = 0;
+        for _ in 0..2 {
+            match self.peek_char().and_then(|c| c.to_digit(16)) {
+                Some(digit) => {
+                    self.next_char();
+                    value = value * 16 + digit;
+                }
+                None => return Err(self.invalid_token(start)),
+            }
+        }
+        // Every value up to 0xFF is a valid scalar value.
+        Ok(char::from_u32(value).unwrap())
+    }
+
+    /// Lex the `{...}` part of a `\u{1F600}` escape.
+    fn lex_unicode_escape(&mut self, start: usize) -> Result<char, LexError> {
+        if self.peek_char() != Some('{') {
+            return Err(self.invalid_token(start));
+        }
+        self.next_char();
+
+        let mut value: u32 = 0;
+        let mut digits = 0;
+        while let Some(digit) = self.peek_char().and_then(|c| c.to_digit(16)) {
+            self.next_char();
+            digits += 1;
+            if digits > 8 {
+                return Err(self.invalid_token(start));
+            }
+            value = value * 16 + digit;
+        }
+
+        if digits == 0 || self.peek_char() != Some('}') {
+            return Err(self.invalid_token(start));
+        }
+        self.next_char();
+
+        char::from_u32(value).ok_or_else(|| LexError::InvalidUnicodeEscape {
+            value,
+            span: Span::new(self.src_id, start..self.current_pos),
+        })
+    }
+
+    fn lex_number(&mut self, start: usize) -> Result<Token, LexError> {
         let start_pos = self.peek().unwrap().0;
+
+        if self.peek_char() == Some('0') {
+            let radix = match self.peek_nth(1) {
+                Some('x' | 'X') => Some(16),
+                Some('o' | 'O') => Some(8),
+                Some('b' | 'B') => Some(2),
+                _ => None,
+            };
+            if let Some(radix) = radix {
+                return self.lex_radix_number(start, radix);
+            }
+        }
+
         let mut has_dot = false;
         let mut has_exponent = false;
+        let mut prev_digit = false;
+        let mut bad_separator = None;
+        let mut missing_exponent = false;
 
         // Consume all valid number characters
-        while let Some((_, c)) = self.peek() {
+        while let Some((pos, c)) = self.peek() {
             match c {
                 '.' if !has_dot && !has_exponent => {
                     has_dot = true;
+                    prev_digit = false;
                     self.next_char();
                 }
                 '0'..='9' => {
+                    prev_digit = true;
+                    self.next_char();
+                }
+                '_' => {
                     self.next_char();
+                    let next_digit = matches!(self.peek_char(), Some('0'..='9'));
+                    if bad_separator.is_none() && !(prev_digit && next_digit) {
+                        bad_separator = Some(Span::new(self.src_id, pos..self.current_pos));
+                    }
+                    prev_digit = false;
                 }
                 'e' | 'E' if !has_exponent => {
                     has_dot = true;
                     has_exponent = true;
+                    prev_digit = false;
                     self.next_char();
                     if matches!(self.peek_char(), Some('+' | '-')) {
                         self.next_char();
                     }
+                    missing_exponent = !matches!(self.peek_char(), Some('0'..='9' | '_'));
                 }
                 _ => break,
             }
         }
 
+        if let Some(span) = bad_separator {
+            return Err(LexError::InvalidDigitSeparator(span));
+        }
+
         let end_pos = self.current_pos;
-        let num_str = &self.source[start_pos..end_pos];
+        let lexeme = &self.source[start_pos..end_pos];
+        let span = Span::new(self.src_id, start..end_pos);
+
+        if missing_exponent {
+            return Err(LexError::InvalidFloat(lexeme.to_string(), span));
+        }
+
+        let num_str = lexeme.replace('_', "");
 
         if has_dot || has_exponent {
-            num_str
-                .parse::<f64>()
-                .map(Token::Real)
-                .map_err(|_| Span::new(self.src_id, start..end_pos))
+            match num_str.parse::<f64>() {
+                // Literals too small to represent round to zero, but ones too
+                // large to represent are rejected rather than becoming infinity.
+                Ok(value) if value.is_infinite() => Err(LexError::FloatOverflow {
+                    literal: lexeme.to_string(),
+                    span,
+                }),
+                Ok(value) => Ok(Token::Real(value)),
+                Err(_) => Err(LexError::InvalidFloat(lexeme.to_string(), span)),
+            }
         } else {
             num_str
-                .parse::<usize>()
+                .parse::<i64>()
                 .map(Token::Int)
-                .map_err(|_| Span::new(self.src_id, start..end_pos))
+                .map_err(|err| self.int_error(err, lexeme, span))
         }
     }
 
-    fn lex_ident(&mut self) -> Result<Token, Span> {
-        let start_pos = self.peek().unwrap().0;
+    /// Lex a `0x`, `0o` or `0b` prefixed integer literal.
+    fn lex_radix_number(&mut self, start: usize, radix: u32) -> Result<Token, LexError> {
+        self.next_char(); // consume '0'
+        self.next_char(); // consume radix prefix
+        let digits_start = self.current_pos;
+        let mut invalid = None;
+        let mut bad_separator = None;
+        let mut prev_digit = false;
 
-        while let Some((_, c)) = self.peek() {
-            if c.is_alphanumeric() || c == '_' {
+        while let Some((pos, c)) = self.peek() {
+            if c == '_' {
                 self.next_char();
-            } else {
+                let next_digit = self.peek_char().is_some_and(|c| c.is_ascii_alphanumeric());
+                if bad_separator.is_none() && !(prev_digit && next_digit) {
+                    bad_separator = Some(Span::new(self.src_id, pos..self.current_pos));
+                }
+                prev_digit = false;
+                continue;
+            }
+            if !c.is_ascii_alphanumeric() {
                 break;
             }
+            self.next_char();
+            prev_digit = true;
+            if invalid.is_none() && !c.is_digit(radix) {
+                invalid = Some(Span::new(self.src_id, pos..self.current_pos));
+            }
+        }
+
+        let end_pos = self.current_pos;
+        let span = Span::new(self.src_id, start..end_pos);
+
+        if digits_start == end_pos {
+            return Err(LexError::EmptyRadixLiteral(span));
+        }
+        if let Some(span) = bad_separator {
+            return Err(LexError::InvalidDigitSeparator(span));
+        }
+        if let Some(span) = invalid {
+            return Err(LexError::InvalidNumberChar(span));
+        }
+
+        let digits = self.source[digits_start..end_pos].replace('_', "");
+        i64::from_str_radix(&digits, radix)
+            .map(Token::Int)
+            .map_err(|err| self.int_error(err, &self.source[start..end_pos], span))
+    }
+
+    fn int_error(&self, err: ParseIntError, lexeme: &str, span: Span) -> LexError {
+        match err.kind() {
+            IntErrorKind::PosOverflow => LexError::IntLiteralOverflow {
+                literal: lexeme.to_string(),
+                max: i64::MAX,
+                span,
+            },
+            _ => LexError::InvalidInt(lexeme.to_string(), span),
+        }
+    }
+
+    fn lex_ident(&mut self) -> Result<Token, Span> {
+        let start_pos = self.peek().unwrap().0;
+
+        loop {
+            self.skip_ascii_while(|b| b.is_ascii_alphanumeric() || b == b'_');
+            match self.peek_char() {
+                Some(c) if c.is_alphanumeric() => {
+                    self.next_char();
+                }
+                _ => break,
+            }
         }
 
         let end_pos = self.current_pos;
@@ -453,34 +1364,29 @@ impl<'src> Lexer<'src> {
         Ok(self.classify_ident(ident))
     }
 
+    /// A bare `_` is the wildcard, as in SML; `_x` and `x_1` are identifiers.
     fn classify_ident(&self, ident: &str) -> Token {
         match ident {
-            "fun" => Token::KwFun,
-            "int" => Token::KwInt,
-            "bool" => Token::KwBool,
-            "real" => Token::KwReal,
-            "char" => Token::KwChar,
-            "unit" => Token::KwUnit,
+            "_" => Token::Underscore,
             "true" => Token::Bool(true),
             "false" => Token::Bool(false),
-            "val" => Token::KwVal,
-            "let" => Token::KwLet,
-            "in" => Token::KwIn,
-            "end" => Token::KwEnd,
-            "if" => Token::KwIf,
-            "then" => Token::KwThen,
-            "else" => Token::KwElse,
-            "not" => Token::KwNot,
-            "mut" => Token::KwMut,
-            "do" => Token::KwDo,
-            "while" => Token::KwWhile,
-            "mod" => Token::KwMod,
-            "div" => Token::KwDiv,
-            _ => Token::Ident(Intern::new(ident.to_string())),
+            _ => self
+                .config
+                .keyword(ident)
+                .unwrap_or_else(|| Token::Ident(Intern::new(ident.to_string()))),
         }
     }
 }
 
+/// Yields tokens one at a time, ending with a single `Eof`.
+impl Iterator for Lexer<'_> {
+    type Item = Result<Spanned<Token>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -488,21 +1394,24 @@ mod tests {
     #[test]
     fn test_basic_tokens() {
         let src_id = SourceId::default();
-        let lexer = Lexer::new(src_id, "( ) , = ::");
+        let lexer = Lexer::new(src_id, "( ) , = :: ; [ ]", LexerConfig::default());
         let tokens = lexer.tokenize().unwrap();
-        assert_eq!(tokens.len(), 6); // 5 tokens + EOF
+        assert_eq!(tokens.len(), 9); // 8 tokens + EOF
         assert_eq!(tokens[0].0, Token::LParen);
         assert_eq!(tokens[1].0, Token::RParen);
         assert_eq!(tokens[2].0, Token::Comma);
         assert_eq!(tokens[3].0, Token::Eq);
         assert_eq!(tokens[4].0, Token::Cons);
-        assert_eq!(tokens[5].0, Token::Eof);
+        assert_eq!(tokens[5].0, Token::Semi);
+        assert_eq!(tokens[6].0, Token::LBracket);
+        assert_eq!(tokens[7].0, Token::RBracket);
+        assert_eq!(tokens[8].0, Token::Eof);
     }
 
     #[test]
     fn test_numbers() {
         let src_id = SourceId::default();
-        let lexer = Lexer::new(src_id, "42 3.14 .5 1e10 2.5e-3");
+        let lexer = Lexer::new(src_id, "42 3.14 .5 1e10 2.5e-3", LexerConfig::default());
         let tokens = lexer.tokenize().unwrap();
         assert_eq!(tokens[0].0, Token::Int(42));
         assert_eq!(tokens[2].0, Token::Real(0.5));
@@ -511,7 +1420,11 @@ mod tests {
     #[test]
     fn test_keywords() {
         let src_id = SourceId::default();
-        let lexer = Lexer::new(src_id, "fun if then else true false");
+        let lexer = Lexer::new(
+            src_id,
+            "fun if then else true false",
+            LexerConfig::default(),
+        );
         let tokens = lexer.tokenize().unwrap();
         assert_eq!(tokens[0].0, Token::KwFun);
         assert_eq!(tokens[1].0, Token::KwIf);
@@ -524,11 +1437,853 @@ mod tests {
     #[test]
     fn test_char_literals() {
         let src_id = SourceId::default();
-        let lexer = Lexer::new(src_id, r"'a' '\n' '\'' '\\'");
+        let lexer = Lexer::new(src_id, r"'a' '\n' '\'' '\\'", LexerConfig::default());
         let tokens = lexer.tokenize().unwrap();
         assert_eq!(tokens[0].0, Token::Char('a'));
         assert_eq!(tokens[1].0, Token::Char('\n'));
         assert_eq!(tokens[2].0, Token::Char('\''));
         assert_eq!(tokens[3].0, Token::Char('\\'));
     }
+
+    #[test]
+    fn test_block_comments() {
+        let src_id = SourceId::default();
+        let lexer = Lexer::new(
+            src_id,
+            "1 (* a (* b *) c *) 2 (**) 3",
+            LexerConfig::default(),
+        );
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[0].0, Token::Int(1));
+        assert_eq!(tokens[1].0, Token::Int(2));
+        assert_eq!(tokens[2].0, Token::Int(3));
+        assert_eq!(tokens[3].0, Token::Eof);
+    }
+
+    #[test]
+    fn test_unterminated_comment() {
+        let src_id = SourceId::default();
+        let lexer = Lexer::new(src_id, "1 (* a (* b *) c", LexerConfig::default());
+        let errors = lexer.tokenize().unwrap_err();
+        assert_eq!(
+            errors,
+            vec![LexError::UnterminatedComment(Span::new(src_id, 2..4))]
+        );
+    }
+
+    #[test]
+    fn test_radix_literals() {
+        let src_id = SourceId::default();
+        let lexer = Lexer::new(src_id, "0xFF 0o17 0b1010 0 017 10", LexerConfig::default());
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens[0].0, Token::Int(255));
+        assert_eq!(tokens[1].0, Token::Int(15));
+        assert_eq!(tokens[2].0, Token::Int(10));
+        assert_eq!(tokens[3].0, Token::Int(0));
+        assert_eq!(tokens[4].0, Token::Int(17));
+        assert_eq!(tokens[5].0, Token::Int(10));
+    }
+
+    #[test]
+    fn test_radix_literal_errors() {
+        let src_id = SourceId::default();
+        let errors = Lexer::new(src_id, "0x", LexerConfig::default())
+            .tokenize()
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![LexError::EmptyRadixLiteral(Span::new(src_id, 0..2))]
+        );
+
+        let errors = Lexer::new(src_id, "0b102", LexerConfig::default())
+            .tokenize()
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![LexError::InvalidNumberChar(Span::new(src_id, 4..5))]
+        );
+    }
+
+    #[test]
+    fn test_digit_separators() {
+        let src_id = SourceId::default();
+        let lexer = Lexer::new(
+            src_id,
+            "1_000_000 1.234_567 1_0e1_0 0xFF_FF 0b1010_1010",
+            LexerConfig::default(),
+        );
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens[0].0, Token::Int(1_000_000));
+        assert_eq!(tokens[1].0, Token::Real(1.234_567));
+        assert_eq!(tokens[2].0, Token::Real(10e10));
+        assert_eq!(tokens[3].0, Token::Int(0xFFFF));
+        assert_eq!(tokens[4].0, Token::Int(0b1010_1010));
+    }
+
+    #[test]
+    fn test_digit_separator_errors() {
+        let src_id = SourceId::default();
+        for (input, range) in [
+            ("1__0", 1..2),
+            ("5_", 1..2),
+            ("1_.5", 1..2),
+            ("1._5", 2..3),
+            ("1e_5", 2..3),
+            ("0x_FF", 2..3),
+        ] {
+            let errors = Lexer::new(src_id, input, LexerConfig::default())
+                .tokenize()
+                .unwrap_err();
+            assert_eq!(
+                errors,
+                vec![LexError::InvalidDigitSeparator(Span::new(src_id, range))],
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_arrow() {
+        let src_id = SourceId::default();
+        let lexer = Lexer::new(src_id, "-> - > -", LexerConfig::default());
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens[0].0, Token::Arrow);
+        assert_eq!(tokens[0].1.range, 0..2);
+        assert_eq!(tokens[1].0, Token::Minus);
+        assert_eq!(tokens[2].0, Token::Gt);
+        assert_eq!(tokens[3].0, Token::Minus);
+    }
+
+    #[test]
+    fn test_token_stream() {
+        let src_id = SourceId::default();
+        let mut lexer = Lexer::new(
+            src_id,
+            "1 + 2 $ this is never reached",
+            LexerConfig::default(),
+        );
+        let first: Vec<_> = lexer.by_ref().take(3).map(|t| t.unwrap().0).collect();
+        assert_eq!(first, [Token::Int(1), Token::Plus, Token::Int(2)]);
+        assert!(matches!(
+            lexer.next(),
+            Some(Err(LexError::UnexpectedChar('$', _)))
+        ));
+
+        let tokens: Vec<_> = Lexer::new(src_id, "x", LexerConfig::default()).collect();
+        assert_eq!(tokens.len(), 2);
+        assert!(matches!(tokens[1], Ok((Token::Eof, _))));
+    }
+
+    #[test]
+    fn test_token_categories() {
+        let cases = [
+            (Token::KwFun, "keyword"),
+            (Token::KwMod, "keyword"),
+            (Token::KwNot, "keyword"),
+            (Token::Plus, "operator"),
+            (Token::ColonEq, "operator"),
+            (Token::Arrow, "operator"),
+            (Token::Int(1), "literal"),
+            (Token::Bool(true), "literal"),
+            (Token::Char('c'), "literal"),
+            (Token::Ident(Intern::from_ref("x")), "other"),
+            (Token::LParen, "other"),
+            (Token::Colon, "other"),
+            (Token::Eof, "other"),
+        ];
+        for (token, category) in cases {
+            let actual = match (token.is_keyword(), token.is_operator(), token.is_literal()) {
+                (true, false, false) => "keyword",
+                (false, true, false) => "operator",
+                (false, false, true) => "literal",
+                (false, false, false) => "other",
+                _ => panic!("{token:?} is in more than one category"),
+            };
+            assert_eq!(actual, category, "token: {token:?}");
+        }
+    }
+
+    #[test]
+    fn test_debug_tokens() {
+        let src = "42 ::";
+        let tokens = Lexer::new(SourceId::default(), src, LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        assert_eq!(
+            debug_tokens(src, &tokens),
+            "Int(42) @ 0..2 \"42\"\nCons @ 3..5 \"::\"\nEof @ 5..5 \"\"\n"
+        );
+    }
+
+    #[test]
+    fn test_multi_byte_spans() {
+        let src_id = SourceId::default();
+        let tokens = Lexer::new(src_id, "café 'λ' x", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        assert_eq!(tokens[0].0, Token::Ident(Intern::from_ref("café")));
+        assert_eq!(tokens[0].1.len(), "café".len());
+        assert_eq!(tokens[0].1.range, 0..5);
+        assert_eq!(tokens[1], (Token::Char('λ'), Span::new(src_id, 6..10)));
+        assert_eq!(tokens[2].1.range, 11..12);
+    }
+
+    #[test]
+    fn test_whitespace_heavy_input() {
+        let compact = "fun café x = x + 1 val y = café 2";
+        let spaced =
+            "\t\tfun   café\r\n\n x\u{a0}=\u{b}x    +\t1\u{2003}\r\n val y=café\n\n\n2   \n";
+        let lex = |src| -> Vec<_> {
+            let tokens = Lexer::new(SourceId::default(), src, LexerConfig::default())
+                .tokenize()
+                .unwrap();
+            tokens
+                .into_iter()
+                .map(|(token, span)| (token, src[span.range].to_string()))
+                .collect()
+        };
+        let compact = lex(compact);
+        assert_eq!(compact.len(), 13);
+        assert_eq!(lex(spaced), compact);
+    }
+
+    #[test]
+    fn test_char_display_escapes() {
+        assert_eq!(Token::Char('\n').to_string(), "'\\n'");
+        assert_eq!(Token::Char('\'').to_string(), "'\\''");
+        assert_eq!(Token::Char('\u{7f}').to_string(), "'\\u{7f}'");
+        assert_eq!(Token::Char('λ').to_string(), "'λ'");
+
+        for c in [
+            '\n', '\r', '\t', '\0', '\'', '\\', '"', '\u{1b}', '\u{7f}', 'a', 'λ',
+        ] {
+            let src = Token::Char(c).to_string();
+            let tokens = Lexer::new(SourceId::default(), &src, LexerConfig::default())
+                .tokenize()
+                .unwrap();
+            assert_eq!(tokens[0].0, Token::Char(c), "{src}");
+        }
+    }
+
+    #[test]
+    fn test_lexer_config() {
+        let src = "a andalso b div c && d % e";
+        let lex = |config| -> Vec<_> {
+            Lexer::new(SourceId::default(), src, config)
+                .map(|result| result.map(|(t, _)| t))
+                .collect()
+        };
+        let ident = |s| Ok(Token::Ident(Intern::from_ref(s)));
+
+        assert_eq!(
+            lex(LexerConfig::default()),
+            [
+                ident("a"),
+                Ok(Token::AndAnd),
+                ident("b"),
+                Ok(Token::KwDiv),
+                ident("c"),
+                Ok(Token::AndAnd),
+                ident("d"),
+                Ok(Token::Percent),
+                ident("e"),
+                Ok(Token::Eof)
+            ]
+        );
+        let no_words = LexerConfig {
+            logical_words: false,
+            arithmetic_words: false,
+            ..LexerConfig::default()
+        };
+        assert_eq!(
+            lex(no_words),
+            [
+                ident("a"),
+                ident("andalso"),
+                ident("b"),
+                ident("div"),
+                ident("c"),
+                Ok(Token::AndAnd),
+                ident("d"),
+                Ok(Token::Percent),
+                ident("e"),
+                Ok(Token::Eof)
+            ]
+        );
+        let no_symbols = LexerConfig {
+            logical_symbols: false,
+            arithmetic_symbols: false,
+            ..LexerConfig::default()
+        };
+        assert_eq!(
+            lex(no_symbols),
+            [
+                ident("a"),
+                Ok(Token::AndAnd),
+                ident("b"),
+                Ok(Token::KwDiv),
+                ident("c"),
+                Ok(Token::And),
+                Ok(Token::And),
+                ident("d"),
+                Err(LexError::UnexpectedChar(
+                    '%',
+                    Span::new(SourceId::default(), 23..24)
+                )),
+                ident("e"),
+                Ok(Token::Eof)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_keywords() {
+        let plain = LexerConfig {
+            logical_words: false,
+            arithmetic_words: false,
+            ..LexerConfig::default()
+        };
+        for word in ["div", "mod", "andalso", "orelse"] {
+            assert!(LexerConfig::default().is_keyword(word), "{word}");
+            assert!(!plain.is_keyword(word), "{word}");
+        }
+        assert_eq!(LexerConfig::default().keyword("orelse"), Some(Token::Or));
+        assert_eq!(plain.keyword("val"), Some(Token::KwVal));
+        assert!(is_keyword_str("andalso"));
+    }
+
+    #[test]
+    fn test_type_var_or_char() {
+        let src_id = SourceId::default();
+        let tokens = Lexer::new(src_id, "'a 'a' 'key_1 '_' 'b", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        assert_eq!(
+            tokens,
+            [
+                (
+                    Token::TypeVar(Intern::from_ref("a")),
+                    Span::new(src_id, 0..2)
+                ),
+                (Token::Char('a'), Span::new(src_id, 3..6)),
+                (
+                    Token::TypeVar(Intern::from_ref("key_1")),
+                    Span::new(src_id, 7..13)
+                ),
+                (Token::Char('_'), Span::new(src_id, 14..17)),
+                (
+                    Token::TypeVar(Intern::from_ref("b")),
+                    Span::new(src_id, 18..20)
+                ),
+                (Token::Eof, Span::point(src_id, 20)),
+            ]
+        );
+        assert_eq!(tokens[0].0.to_string(), "'a");
+
+        assert!(
+            Lexer::new(src_id, "'1", LexerConfig::default())
+                .tokenize()
+                .is_err()
+        );
+        assert!(
+            Lexer::new(src_id, "' a", LexerConfig::default())
+                .tokenize()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_underscore() {
+        let tokens = Lexer::new(SourceId::default(), "_ _x x_1 __", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let tokens: Vec<_> = tokens.into_iter().map(|(token, _)| token).collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Underscore,
+                Token::Ident(Intern::from_ref("_x")),
+                Token::Ident(Intern::from_ref("x_1")),
+                Token::Ident(Intern::from_ref("__")),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keyword_table() {
+        for (keyword, token) in KEYWORDS.iter().chain(ARITHMETIC_WORDS) {
+            assert_eq!(token.to_string(), *keyword);
+            assert!(token.is_keyword(), "{token:?}");
+            assert!(is_keyword_str(keyword));
+
+            let tokens = Lexer::new(SourceId::default(), keyword, LexerConfig::default())
+                .tokenize()
+                .unwrap();
+            assert_eq!(&tokens[0].0, token);
+        }
+        for (keyword, token) in LOGICAL_WORDS {
+            let tokens = Lexer::new(SourceId::default(), keyword, LexerConfig::default())
+                .tokenize()
+                .unwrap();
+            assert_eq!(&tokens[0].0, token);
+        }
+        assert!(!is_keyword_str("value"));
+        assert!(!is_keyword_str("true"));
+    }
+
+    #[test]
+    fn test_multi_byte_input_never_panics() {
+        let prefixes = [
+            "", "1", "1_", "0x", "0b1", "1.", "1.5e", "1.5e+", "x", "x_", "'", "'\\", "'\\u{",
+            "'\\x", "(*", "&", "|", ":", "<",
+        ];
+        let suffixes = ["é", "λ", "😀", "é1", "😀x"];
+        for prefix in prefixes {
+            for suffix in suffixes {
+                let input = format!("{prefix}{suffix}");
+                // Any result is fine, as long as every span is a valid slice.
+                for token in Lexer::new(SourceId::default(), &input, LexerConfig::default()) {
+                    let range = match &token {
+                        Ok((_, span)) => span.range.clone(),
+                        Err(err) => {
+                            let label = err.labels().unwrap().next().unwrap();
+                            label.offset()..label.offset() + label.len()
+                        }
+                    };
+                    assert!(input.get(range).is_some(), "input: {input:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ampersand_and_bar() {
+        let src_id = SourceId::default();
+        let lex = |input| {
+            Lexer::new(src_id, input, LexerConfig::default())
+                .tokenize()
+                .map(|tokens| {
+                    tokens
+                        .into_iter()
+                        .map(|(token, _)| token)
+                        .collect::<Vec<_>>()
+                })
+        };
+        assert_eq!(lex("&"), Ok(vec![Token::And, Token::Eof]));
+        assert_eq!(lex("&&"), Ok(vec![Token::AndAnd, Token::Eof]));
+        assert_eq!(lex("&&&"), Ok(vec![Token::AndAnd, Token::And, Token::Eof]));
+        assert_eq!(lex("||"), Ok(vec![Token::Or, Token::Eof]));
+        assert_eq!(
+            lex("andalso orelse"),
+            Ok(vec![Token::AndAnd, Token::Or, Token::Eof])
+        );
+        assert_eq!(
+            lex("| => ="),
+            Ok(vec![Token::Bar, Token::FatArrow, Token::Eq, Token::Eof])
+        );
+        assert_eq!(lex("|||"), Ok(vec![Token::Or, Token::Bar, Token::Eof]));
+    }
+
+    #[test]
+    fn test_token_kind() {
+        assert_eq!(Token::Int(1).kind(), Token::Int(42).kind());
+        assert_eq!(Token::Real(1.0).kind(), TokenKind::Real);
+        assert_eq!(
+            Token::Ident(Intern::from_ref("x")).kind(),
+            Token::Ident(Intern::from_ref("y")).kind()
+        );
+        assert_ne!(Token::Int(1).kind(), Token::Real(1.0).kind());
+        assert_ne!(Token::Bool(true).kind(), Token::KwBool.kind());
+        assert_eq!(Token::RParen.kind(), TokenKind::RParen);
+
+        assert_eq!(TokenKind::RParen.to_string(), ")");
+        assert_eq!(TokenKind::ColonEq.to_string(), Token::ColonEq.to_string());
+        assert_eq!(TokenKind::Or.to_string(), "||");
+        assert_eq!(TokenKind::And.to_string(), "&");
+        assert_eq!(TokenKind::Int.to_string(), "integer literal");
+        assert_eq!(TokenKind::Eof.to_string(), "end of input");
+    }
+
+    #[test]
+    fn test_eof_span() {
+        let src_id = SourceId::default();
+        let tokens = Lexer::new(src_id, "x  ", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        assert_eq!(tokens[1], (Token::Eof, Span::point(src_id, 3)));
+    }
+
+    /// Apply `edit` to `old`, then check `relex_range` against a full re-lex.
+    fn assert_relex(old: &str, range: Range<usize>, insert: &str) {
+        let src_id = SourceId::default();
+        let new = format!("{}{insert}{}", &old[..range.start], &old[range.end..]);
+        let lexer = Lexer::new(src_id, old, LexerConfig::default());
+        let old_tokens = Lexer::new(src_id, old, LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let edit = Edit {
+            range,
+            new_len: insert.len(),
+        };
+
+        let (mut tokens, mut errors) = (Vec::new(), Vec::new());
+        for result in Lexer::new(src_id, &new, LexerConfig::default()) {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
+            }
+        }
+        assert_eq!(
+            lexer.relex_range(&old_tokens, edit, &new),
+            (tokens, errors),
+            "relexing {new:?}"
+        );
+    }
+
+    #[test]
+    fn test_relex_inside_ident() {
+        let src = "val foo = bar + 1 (* done *)";
+        assert_relex(src, 5..6, "xx");
+        assert_relex(src, 4..7, "q");
+        assert_relex(src, 11..11, "_2");
+        // A quote inside `bar` turns the rest of it into a type variable.
+        assert_relex(src, 11..11, "'");
+    }
+
+    #[test]
+    fn test_relex_at_token_boundary() {
+        // Merging: `a` `=` becomes `a=>`, `foo bar` becomes `foobar`.
+        assert_relex("a = b", 3..3, ">");
+        assert_relex("foo bar", 3..4, "");
+        // Splitting: `foobar` becomes `foo bar`, `=>` becomes `= >`.
+        assert_relex("foobar 1", 3..3, " ");
+        assert_relex("x => 1", 3..3, " ");
+        // Edits at either end of the input.
+        assert_relex("f x", 0..0, "g ");
+        assert_relex("f x", 3..3, "yz");
+        assert_relex("f x (* c *)", 3..11, "");
+        // Opening a comment swallows tokens up to the old `*)`.
+        assert_relex("f x *) + y", 2..2, "(* ");
+    }
+
+    #[test]
+    fn test_relex_reports_errors() {
+        // There are no string literals, so an unterminated char literal and
+        // comment stand in for an unterminated string.
+        assert_relex("f x y", 2..3, "'\\n");
+        assert_relex("f x y", 2..3, "(* x");
+        assert_relex("a + b * c", 4..5, "1__0 $");
+
+        let src_id = SourceId::default();
+        let old = "f x y";
+        let old_tokens = Lexer::new(src_id, old, LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let edit = Edit {
+            range: 4..5,
+            new_len: 2,
+        };
+        let (tokens, errors) = Lexer::new(src_id, old, LexerConfig::default()).relex_range(
+            &old_tokens,
+            edit,
+            "f x '7",
+        );
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(errors, [LexError::InvalidToken(Span::new(src_id, 4..6))]);
+    }
+
+    #[test]
+    fn test_relex_reuses_shifted_tokens() {
+        let src_id = SourceId::default();
+        let old = "a + b * c";
+        let old_tokens = Lexer::new(src_id, old, LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let edit = Edit {
+            range: 0..1,
+            new_len: 4,
+        };
+        let (tokens, errors) = Lexer::new(src_id, old, LexerConfig::default()).relex_range(
+            &old_tokens,
+            edit,
+            "abcd + b * c",
+        );
+        assert_eq!(errors, []);
+        assert_eq!(
+            tokens[0],
+            (
+                Token::Ident(Intern::from_ref("abcd")),
+                Span::new(src_id, 0..4)
+            )
+        );
+        assert_eq!(tokens[3], (Token::Star, Span::new(src_id, 9..10)));
+        assert_eq!(tokens.last(), Some(&(Token::Eof, Span::point(src_id, 12))));
+    }
+
+    #[test]
+    fn test_lexeme() {
+        let src_id = SourceId::default();
+        let mut lexer = Lexer::new(src_id, "1_000 1.50 0xff foo", LexerConfig::default());
+        let tokens: Vec<_> = lexer.by_ref().map(Result::unwrap).collect();
+        assert_eq!(tokens[0].0, Token::Int(1000));
+        assert_eq!(lexer.lexeme(&tokens[0].1), "1_000");
+        assert_eq!(tokens[1].0, Token::Real(1.5));
+        assert_eq!(lexer.lexeme(&tokens[1].1), "1.50");
+        assert_eq!(lexer.lexeme(&tokens[2].1), "0xff");
+        assert_eq!(lexer.lexeme(&tokens[3].1), "foo");
+        assert_eq!(lexer.lexeme(&tokens[4].1), "");
+    }
+
+    #[test]
+    fn test_slash_percent() {
+        let src_id = SourceId::default();
+        let lexer = Lexer::new(src_id, "a / b % c", LexerConfig::default());
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens[1].0, Token::Slash);
+        assert_eq!(tokens[3].0, Token::Percent);
+    }
+
+    #[test]
+    fn test_exponents() {
+        let src_id = SourceId::default();
+        let tokens = Lexer::new(src_id, "1.5e3 2E-10 3e+2", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        assert_eq!(tokens[0].0, Token::Real(1.5e3));
+        assert_eq!(tokens[1].0, Token::Real(2E-10));
+        assert_eq!(tokens[2].0, Token::Real(3e2));
+
+        for input in ["1e", "1e+", "2.5E-"] {
+            let errors = Lexer::new(src_id, input, LexerConfig::default())
+                .tokenize()
+                .unwrap_err();
+            assert_eq!(
+                errors,
+                vec![LexError::InvalidFloat(
+                    input.to_string(),
+                    Span::new(src_id, 0..input.len())
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn test_int_overflow() {
+        let src_id = SourceId::default();
+        let literal = "123456789012345678901234567890";
+        let errors = Lexer::new(src_id, literal, LexerConfig::default())
+            .tokenize()
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![LexError::IntLiteralOverflow {
+                literal: literal.to_string(),
+                max: i64::MAX,
+                span: Span::new(src_id, 0..30),
+            }]
+        );
+
+        let errors = Lexer::new(src_id, "0x8000_0000_0000_0000", LexerConfig::default())
+            .tokenize()
+            .unwrap_err();
+        assert!(matches!(errors[0], LexError::IntLiteralOverflow { .. }));
+
+        let tokens = Lexer::new(
+            src_id,
+            "9223372036854775807 0x7FFF_FFFF_FFFF_FFFF",
+            LexerConfig::default(),
+        )
+        .tokenize()
+        .unwrap();
+        assert_eq!(tokens[0].0, Token::Int(i64::MAX));
+        assert_eq!(tokens[1].0, Token::Int(i64::MAX));
+
+        let errors = Lexer::new(src_id, "9223372036854775808", LexerConfig::default())
+            .tokenize()
+            .unwrap_err();
+        assert!(matches!(errors[0], LexError::IntLiteralOverflow { .. }));
+
+        let errors = Lexer::new(src_id, "$", LexerConfig::default())
+            .tokenize()
+            .unwrap_err();
+        assert!(matches!(errors[0], LexError::UnexpectedChar('$', _)));
+    }
+
+    #[test]
+    fn test_errors_in_source_order() {
+        let src = "(* unclosed $ (* nested *)";
+        let errors = Lexer::new(
+            SourceId::default(),
+            &format!("1_ $ 0x {src}"),
+            LexerConfig::default(),
+        )
+        .tokenize()
+        .unwrap_err();
+        let starts: Vec<_> = errors.iter().map(|e| e.span().start()).collect();
+        assert_eq!(starts, [1, 3, 5, 8]);
+        assert!(matches!(errors[3], LexError::UnterminatedComment(_)));
+    }
+
+    #[test]
+    fn test_float_overflow() {
+        let src_id = SourceId::default();
+        let errors = Lexer::new(src_id, "1e400", LexerConfig::default())
+            .tokenize()
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![LexError::FloatOverflow {
+                literal: "1e400".to_string(),
+                span: Span::new(src_id, 0..5),
+            }]
+        );
+
+        let tokens = Lexer::new(
+            src_id,
+            "1e10 1e-400 1.7976931348623157e308",
+            LexerConfig::default(),
+        )
+        .tokenize()
+        .unwrap();
+        assert_eq!(tokens[0].0, Token::Real(1e10));
+        assert_eq!(tokens[1].0, Token::Real(0.0));
+        assert_eq!(tokens[2].0, Token::Real(f64::MAX));
+    }
+
+    #[test]
+    fn test_report_lex_errors() {
+        let mut db = SourceDatabase::new();
+        let src_id = db.add_file("main.mon", "val x = 0x\nval y = 1__0 $");
+        let errors = Lexer::new(src_id, db.contents(src_id), LexerConfig::default())
+            .tokenize()
+            .unwrap_err();
+        assert_eq!(errors.len(), 3);
+
+        let report = report_lex_errors(&db, src_id, &errors);
+        assert!(report.contains("lex::empty_radix_literal"));
+        assert!(report.contains("lex::invalid_digit_separator"));
+        assert!(report.contains("lex::unexpected_char"));
+        assert!(report.contains("main.mon:1:9"));
+        assert!(report.contains("main.mon:2:14"));
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(Token::Cons.display_width(), 2);
+        assert_eq!(Token::ColonEq.display_width(), 2);
+        assert_eq!(Token::Arrow.display_width(), 2);
+        assert_eq!(Token::KwWhile.display_width(), 5);
+        assert_eq!(Token::Int(1234).display_width(), 4);
+        assert_eq!(Token::Real(2.5).display_width(), 3);
+        assert_eq!(Token::Char('λ').display_width(), 3);
+        assert_eq!(Token::Ident(Intern::from_ref("héllo")).display_width(), 5);
+        assert_eq!(Token::Eof.display_width(), 0);
+    }
+
+    #[test]
+    fn test_numeric_char_escapes() {
+        let src_id = SourceId::default();
+        let lexer = Lexer::new(
+            src_id,
+            r"'\u{41}' '\u{1F600}' '\x41' '\xff'",
+            LexerConfig::default(),
+        );
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens[0].0, Token::Char('A'));
+        assert_eq!(tokens[1].0, Token::Char('😀'));
+        assert_eq!(tokens[2].0, Token::Char('A'));
+        assert_eq!(tokens[3].0, Token::Char('\u{ff}'));
+    }
+
+    #[test]
+    fn test_invalid_unicode_escape() {
+        let src_id = SourceId::default();
+        for (input, value) in [(r"'\u{FFFFFFFF}'", 0xFFFF_FFFF), (r"'\u{D800}'", 0xD800)] {
+            let errors = Lexer::new(src_id, input, LexerConfig::default())
+                .tokenize()
+                .unwrap_err();
+            assert_eq!(
+                errors,
+                vec![LexError::InvalidUnicodeEscape {
+                    value,
+                    span: Span::new(src_id, 0..input.len() - 1),
+                }]
+            );
+        }
+
+        let errors = Lexer::new(src_id, r"'\x4'", LexerConfig::default())
+            .tokenize()
+            .unwrap_err();
+        assert!(matches!(errors[0], LexError::InvalidToken(_)));
+    }
+
+    #[test]
+    fn test_many_dots() {
+        let src_id = SourceId::default();
+        let input = ". .5 ".repeat(10_000);
+        let errors = Lexer::new(src_id, &input, LexerConfig::default())
+            .tokenize()
+            .unwrap_err();
+        assert_eq!(errors.len(), 10_000);
+        assert_eq!(
+            errors[1],
+            LexError::UnexpectedChar('.', Span::new(src_id, 5..6))
+        );
+
+        let tokens: Vec<_> = Lexer::new(src_id, &input, LexerConfig::default())
+            .filter_map(Result::ok)
+            .collect();
+        assert_eq!(tokens.len(), 10_001);
+        assert!(tokens[..10_000].iter().all(|(t, _)| *t == Token::Real(0.5)));
+    }
+
+    #[test]
+    fn test_lookahead_across_trivia() {
+        let src_id = SourceId::default();
+        let lex = |input| -> (Vec<_>, Vec<_>) {
+            let (tokens, errors): (Vec<_>, Vec<_>) =
+                Lexer::new(src_id, input, LexerConfig::default()).partition(Result::is_ok);
+            (
+                tokens.into_iter().map(|t| t.unwrap().0).collect(),
+                errors.into_iter().map(|e| e.unwrap_err()).collect(),
+            )
+        };
+
+        // Lookahead stops at whitespace and comments rather than skipping them.
+        let (tokens, errors) = lex(". 5 .(* c *)5");
+        assert_eq!(tokens, [Token::Int(5), Token::Int(5), Token::Eof]);
+        assert_eq!(errors.len(), 2);
+        let (tokens, _) = lex("( *x");
+        assert_eq!(tokens[..2], [Token::LParen, Token::Star]);
+        let (tokens, _) = lex("'a (* c *) 'b'(* c *)");
+        assert_eq!(
+            tokens,
+            [
+                Token::TypeVar(Intern::from_ref("a")),
+                Token::Char('b'),
+                Token::Eof
+            ]
+        );
+
+        // After trivia is skipped, lookahead starts from the new position.
+        let tokens = Lexer::new(src_id, "  \n(* a (* b *) *)(*c*).5", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        assert_eq!(tokens[0], (Token::Real(0.5), Span::new(src_id, 23..25)));
+    }
+
+    #[test]
+    fn test_unexpected_char() {
+        let src_id = SourceId::default();
+        let errors = Lexer::new(src_id, "x @ y", LexerConfig::default())
+            .tokenize()
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![LexError::UnexpectedChar('@', Span::new(src_id, 2..3))]
+        );
+        assert_eq!(errors[0].to_string(), "unexpected character '@'");
+    }
 }
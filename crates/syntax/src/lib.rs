@@ -1,4 +1,67 @@
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::{
+    ast::{Decl, Expr},
+    lexer::{LexError, Lexer, LexerConfig},
+    parser::{ParseError, Parser, ReplItem},
+    span::{SourceId, Spanned},
+};
+
 pub mod ast;
+pub mod fold;
 pub mod lexer;
+pub mod lint;
 pub mod parser;
 pub mod span;
+
+/// An error from any stage of the syntax frontend.
+#[derive(Debug, Error, Diagnostic)]
+pub enum SyntaxError {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Lex(#[from] LexError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Parse(#[from] ParseError),
+}
+
+fn tokenize(src_id: SourceId, input: &str) -> Result<Parser, Vec<SyntaxError>> {
+    Lexer::new(src_id, input, LexerConfig::default())
+        .tokenize()
+        .map(Parser::new)
+        .map_err(|errors| errors.into_iter().map(SyntaxError::from).collect())
+}
+
+/// Lex and parse `input` as a single expression.
+pub fn parse_str(src_id: SourceId, input: &str) -> Result<Spanned<Expr>, Vec<SyntaxError>> {
+    tokenize(src_id, input)?
+        .parse_code()
+        .map_err(|error| vec![SyntaxError::from(error)])
+}
+
+/// Lex and parse `input` as a sequence of top-level declarations, reporting
+/// every parse error rather than only the first.
+///
+/// A program with no declarations, such as an empty or whitespace-only file,
+/// is valid and yields an empty list.
+pub fn parse_program(
+    src_id: SourceId,
+    input: &str,
+) -> Result<Vec<Spanned<Decl>>, Vec<SyntaxError>> {
+    let (decls, errors) = tokenize(src_id, input)?.parse_program_recovering();
+    if errors.is_empty() {
+        Ok(decls)
+    } else {
+        Err(errors.into_iter().map(SyntaxError::from).collect())
+    }
+}
+
+/// Lex and parse one line of REPL input as either a declaration (when it
+/// starts with `fun` or `val`) or an expression.
+pub fn parse_line(src_id: SourceId, input: &str) -> Result<ReplItem, Vec<SyntaxError>> {
+    tokenize(src_id, input)?
+        .parse_repl_item()
+        .map_err(|error| vec![SyntaxError::from(error)])
+}
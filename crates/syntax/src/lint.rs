@@ -0,0 +1,190 @@
+//! Warnings about code that parses fine but is probably a mistake.
+//!
+//! Lints only report spans; turning them into diagnostics is left to the caller.
+
+use crate::{
+    ast::{BinaryOp, Expr, Func, FuncParam, Ident, Literal, UnaryOp, Visitor, walk_expr},
+    span::{Span, Spanned, SpannedExt},
+};
+
+/// Spans of every `=` or `<>` comparison with a real literal operand, since
+/// exact equality on reals rarely means what was intended.
+pub fn warn_real_equality(expr: &Spanned<Expr>) -> Vec<Span> {
+    struct RealEquality(Vec<Span>);
+
+    impl Visitor for RealEquality {
+        fn visit_binary(
+            &mut self,
+            left: &Spanned<Expr>,
+            op: &Spanned<BinaryOp>,
+            right: &Spanned<Expr>,
+        ) {
+            if matches!(op.0, BinaryOp::Eq | BinaryOp::NotEq)
+                && (is_real_literal(&left.0) || is_real_literal(&right.0))
+            {
+                self.0.push(left.span().merge(right.span()));
+            }
+            self.visit_expr(left);
+            self.visit_expr(right);
+        }
+    }
+
+    let mut lint = RealEquality(Vec::new());
+    walk_expr(&mut lint, expr);
+    lint.0
+}
+
+/// Spans of arithmetic whose operands are an int literal and a real literal,
+/// such as `1 + 2.0`, since neither is implicitly converted to the other.
+pub fn check_numeric_literal_mixing(expr: &Spanned<Expr>) -> Vec<Span> {
+    struct NumericMixing(Vec<Span>);
+
+    impl Visitor for NumericMixing {
+        fn visit_binary(
+            &mut self,
+            left: &Spanned<Expr>,
+            op: &Spanned<BinaryOp>,
+            right: &Spanned<Expr>,
+        ) {
+            let arithmetic = matches!(
+                op.0,
+                BinaryOp::Add
+                    | BinaryOp::Sub
+                    | BinaryOp::Mul
+                    | BinaryOp::Div
+                    | BinaryOp::Rem
+                    | BinaryOp::Pow
+            );
+            let mixed = (is_int_literal(&left.0) && is_real_literal(&right.0))
+                || (is_real_literal(&left.0) && is_int_literal(&right.0));
+            if arithmetic && mixed {
+                self.0.push(left.span().merge(right.span()));
+            }
+            self.visit_expr(left);
+            self.visit_expr(right);
+        }
+    }
+
+    let mut lint = NumericMixing(Vec::new());
+    walk_expr(&mut lint, expr);
+    lint.0
+}
+
+/// Every parameter of `func` that repeats the name of an earlier one, such as
+/// the second `x` in `fun f (x : int) (x : int) = ...`, with its span.
+pub fn check_duplicate_params(func: &Func) -> Vec<(Ident, Span)> {
+    let mut seen: Vec<&Ident> = Vec::new();
+    let mut duplicates = Vec::new();
+    for param in &func.params {
+        let (name, span) = param_name(param);
+        if seen.contains(&name) {
+            duplicates.push((name.clone(), span.clone()));
+        } else {
+            seen.push(name);
+        }
+    }
+    duplicates
+}
+
+/// The name bound by a parameter and the span of the name alone.
+fn param_name((param, span): &Spanned<FuncParam>) -> (&Ident, &Span) {
+    match param {
+        FuncParam::Ident(ident) => (ident, span),
+        FuncParam::Typed { param, .. } => param_name(param),
+    }
+}
+
+fn is_real_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(Literal::Real(_)) => true,
+        Expr::Unary {
+            op: (UnaryOp::Neg, _),
+            expr,
+        } => is_real_literal(&expr.0),
+        _ => false,
+    }
+}
+
+fn is_int_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(Literal::Int(_)) => true,
+        Expr::Unary {
+            op: (UnaryOp::Neg, _),
+            expr,
+        } => is_int_literal(&expr.0),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::Decl, parse_program, parse_str, span::SourceId};
+
+    fn lint(input: &str) -> Vec<Span> {
+        warn_real_equality(&parse_str(SourceId::default(), input).unwrap())
+    }
+
+    #[test]
+    fn test_real_equality_flagged() {
+        assert_eq!(lint("1.0 = 2.0"), [Span::new(0, 0..9)]);
+        assert_eq!(lint("x <> ~0.5"), [Span::new(0, 0..9)]);
+        assert_eq!(
+            lint("if x = 1.5 then 1 else y = 2.0"),
+            [Span::new(0, 3..10), Span::new(0, 23..30)]
+        );
+    }
+
+    #[test]
+    fn test_other_comparisons_not_flagged() {
+        assert!(lint("1 = 2").is_empty());
+        assert!(lint("x = y").is_empty());
+        assert!(lint("1.0 < 2.0").is_empty());
+    }
+
+    #[test]
+    fn test_numeric_mixing_flagged() {
+        let mixing =
+            |input| check_numeric_literal_mixing(&parse_str(SourceId::default(), input).unwrap());
+        assert_eq!(mixing("1 + 2.0"), [Span::new(0, 0..7)]);
+        assert_eq!(mixing("~2.5 * 4"), [Span::new(0, 0..8)]);
+        assert_eq!(mixing("x + (3 div 1.5)"), [Span::new(0, 5..14)]);
+
+        assert!(mixing("1.0 + 2.0").is_empty());
+        assert!(mixing("1 + 2").is_empty());
+        assert!(mixing("x + 2.0").is_empty());
+        assert!(mixing("1 < 2.0").is_empty());
+    }
+
+    fn duplicate_params(input: &str) -> Vec<(String, Span)> {
+        let decls = parse_program(SourceId::default(), input).unwrap();
+        let Decl::Func(func) = &decls[0].0 else {
+            panic!("expected function declaration");
+        };
+        check_duplicate_params(func)
+            .into_iter()
+            .map(|(name, span)| (name.to_string(), span))
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicate_params() {
+        assert_eq!(
+            duplicate_params("fun f (x : int) (x : int) = x"),
+            [("x".to_string(), Span::new(0, 17..18))]
+        );
+        assert_eq!(
+            duplicate_params("fun f x y x (y : int) = x"),
+            [
+                ("x".to_string(), Span::new(0, 10..11)),
+                ("y".to_string(), Span::new(0, 13..14)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unique_params_not_flagged() {
+        assert!(duplicate_params("fun f x (y : int) z = x").is_empty());
+        assert!(duplicate_params("fun f = 1").is_empty());
+    }
+}
@@ -2,20 +2,24 @@ use miette::{Diagnostic, SourceSpan};
 use thiserror::Error;
 
 use crate::{
-    ast::{BinaryOp, BorrowOp, Expr, Ident, Literal, Type, UnaryOp},
-    lexer::Token,
-    span::{Span, Spanned, SpannedExt},
+    ast::{
+        Arm, BinaryOp, BorrowOp, Decl, Expr, Func, FuncParam, Ident, Literal, Pattern, Stmt, Type,
+        UnaryOp, Val,
+    },
+    lexer::{Token, TokenKind},
+    span::{Span, Spanned, SpannedExt, map_spanned},
 };
 
-#[derive(Debug, Error, Diagnostic)]
+#[derive(Debug, Clone, Error, Diagnostic)]
 pub enum ParseError {
-    #[error("expected {expected}, found {found}")]
+    #[error("expected {}, found {}", one_of(expected), describe(found))]
     #[diagnostic(
         code(parse::unexpected_token),
         help("ensure the token order matches the grammar")
     )]
     UnexpectedToken {
-        expected: Token,
+        /// Every kind of token that would have been accepted here.
+        expected: Vec<TokenKind>,
         found: Token,
         #[label("here")]
         span: SourceSpan,
@@ -26,7 +30,10 @@ pub enum ParseError {
         code(parse::unexpected_eof),
         help("try adding a missing expression or closing delimiter")
     )]
-    UnexpectedEOF,
+    UnexpectedEOF {
+        #[label("input ends here")]
+        span: SourceSpan,
+    },
 
     #[error("expected type, found {found}")]
     #[diagnostic(
@@ -39,6 +46,36 @@ pub enum ParseError {
         span: SourceSpan,
     },
 
+    #[error("expected pattern, found {found}")]
+    #[diagnostic(
+        code(parse::expected_pattern),
+        help("a case arm starts with `_` or a literal, e.g. `0 => ...`")
+    )]
+    ExpectedPattern {
+        found: Token,
+        #[label("pattern expected here")]
+        span: SourceSpan,
+    },
+
+    #[error("expected identifier, found {found}")]
+    #[diagnostic(code(parse::expected_ident), help("a name was expected here"))]
+    ExpectedIdent {
+        found: Token,
+        #[label("identifier expected here")]
+        span: SourceSpan,
+    },
+
+    #[error("`{keyword}` is a reserved keyword and cannot be used as a name")]
+    #[diagnostic(
+        code(parse::reserved_keyword),
+        help("`{keyword}` is reserved; choose a different name, e.g. `{keyword}_`")
+    )]
+    ReservedKeywordAsIdent {
+        keyword: Token,
+        #[label("reserved keyword")]
+        span: SourceSpan,
+    },
+
     #[error("expected expression")]
     #[diagnostic(
         code(parse::expected_primary),
@@ -49,6 +86,48 @@ pub enum ParseError {
         span: SourceSpan,
     },
 
+    #[error("expected statement, found {found}")]
+    #[diagnostic(
+        code(parse::expected_statement),
+        help("expected a `val` or `fun` binding, an assignment, or a `while` loop")
+    )]
+    ExpectedStatement {
+        found: Token,
+        #[label("here")]
+        span: SourceSpan,
+    },
+
+    #[error("invalid assignment target")]
+    #[diagnostic(
+        code(parse::invalid_assign_target),
+        help("only a variable name may appear on the left of `:=`")
+    )]
+    InvalidAssignTarget {
+        #[label("cannot assign to this expression")]
+        span: SourceSpan,
+    },
+
+    #[error("comparison operators cannot be chained")]
+    #[diagnostic(
+        code(parse::chained_comparison),
+        help("add parentheses to make the grouping explicit, e.g. `(a < b) = c`")
+    )]
+    ChainedComparison {
+        #[label("chained comparison")]
+        span: SourceSpan,
+    },
+
+    #[error("expected declaration, found {found}")]
+    #[diagnostic(
+        code(parse::expected_decl),
+        help("top-level items must be `fun` or `val` declarations")
+    )]
+    ExpectedDecl {
+        found: Token,
+        #[label("here")]
+        span: SourceSpan,
+    },
+
     #[error("expected `{expected}`")]
     #[diagnostic(
         code(parse::expected_delimiter),
@@ -64,15 +143,133 @@ pub enum ParseError {
         #[label("parser reached here")]
         end_span: SourceSpan,
     },
+
+    #[error("expression is nested too deeply")]
+    #[diagnostic(
+        code(parse::nesting_too_deep),
+        help("split the expression into smaller `val` bindings")
+    )]
+    NestingTooDeep {
+        #[label("nesting limit reached here")]
+        span: SourceSpan,
+    },
 }
 
 pub type ParserResult<T> = Result<T, ParseError>;
 
+/// Render a list of token kinds for a diagnostic, e.g. `` `)` or `,` ``.
+fn one_of(kinds: &[TokenKind]) -> String {
+    let kinds: Vec<_> = kinds
+        .iter()
+        .map(|kind| match kind {
+            // These name a class of tokens rather than spelling one out.
+            TokenKind::Real
+            | TokenKind::Int
+            | TokenKind::Bool
+            | TokenKind::Char
+            | TokenKind::Ident
+            | TokenKind::Eof => kind.to_string(),
+            _ => format!("`{kind}`"),
+        })
+        .collect();
+    match kinds.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {last}", rest.join(", ")),
+        None => "nothing".to_string(),
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Eof => TokenKind::Eof.to_string(),
+        _ => format!("`{token}`"),
+    }
+}
+
+/// A single line of REPL input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplItem {
+    Expr(Spanned<Expr>),
+    Decl(Spanned<Decl>),
+}
+
+/// How a binary operator groups with others of the same precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+    /// Chaining is an error, as with comparisons.
+    None,
+}
+
+/// The precedence (higher binds tighter) and associativity of `op`, which
+/// drive `Parser::parse_binary`.
+pub fn precedence(op: BinaryOp) -> (u8, Associativity) {
+    match op {
+        BinaryOp::LogicalOr => (1, Associativity::Left),
+        BinaryOp::LogicalAnd => (2, Associativity::Left),
+        BinaryOp::Eq
+        | BinaryOp::NotEq
+        | BinaryOp::Less
+        | BinaryOp::LessEq
+        | BinaryOp::Greater
+        | BinaryOp::GreaterEq => (3, Associativity::None),
+        BinaryOp::Cons => (4, Associativity::Right),
+        BinaryOp::Add | BinaryOp::Sub => (5, Associativity::Left),
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => (6, Associativity::Left),
+        BinaryOp::Pow => (7, Associativity::Right),
+    }
+}
+
+/// How deeply expressions, types and statements may nest before the parser
+/// gives up with [`ParseError::NestingTooDeep`] rather than overflowing the stack.
+///
+/// This is well beyond what hand-written code reaches. Every level costs a
+/// handful of stack frames, under 8 KiB in an unoptimized build, so the limit
+/// still fits in the 2 MiB stack of a spawned thread.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// The name, parameters and return type of a function, before its body.
+type FuncHead = (
+    Spanned<Ident>,
+    Vec<Spanned<FuncParam>>,
+    Option<Spanned<Type>>,
+);
+
+/// An item inside parentheses, before a `,` or `;` has settled whether it
+/// belongs to a tuple or a sequence.
+///
+/// Statements are boxed because they are much larger than expressions, and
+/// every frame of [`Parser::parse_seq`] and its helpers holds an item.
+enum SeqItem {
+    Expr(Spanned<Expr>),
+    Stmt(Box<Spanned<Stmt>>),
+}
+
+impl SeqItem {
+    /// The item as a statement, run only for its effect.
+    fn into_stmt(self) -> Spanned<Stmt> {
+        match self {
+            SeqItem::Stmt(stmt) => *stmt,
+            SeqItem::Expr(expr) => {
+                let span = expr.span();
+                (Stmt::Expr(expr), span)
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Parser {
     tokens: Vec<Spanned<Token>>,
     pos: usize,
     len: usize,
+    depth: usize,
+    max_depth: usize,
+    /// Set while parsing in recovering mode, where some errors are recorded in
+    /// `recovered` and patched over instead of aborting the declaration.
+    recovering: bool,
+    recovered: Vec<ParseError>,
 }
 
 impl Parser {
@@ -81,7 +278,52 @@ impl Parser {
             len: tokens.len(),
             tokens,
             pos: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            recovering: false,
+            recovered: Vec::new(),
+        }
+    }
+
+    /// Override the nesting limit, which defaults to [`DEFAULT_MAX_DEPTH`].
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Whether every token but the final `Eof` has been consumed.
+    pub fn at_eof(&self) -> bool {
+        *self.peek() == Token::Eof
+    }
+
+    /// The tokens not yet consumed, always ending with `Eof`.
+    pub fn remaining(&self) -> &[Spanned<Token>] {
+        &self.tokens[self.pos..]
+    }
+
+    /// The current position, for [`Parser::restore`] to return to if a
+    /// speculative parse fails.
+    pub fn checkpoint(&self) -> usize {
+        self.pos
+    }
+
+    /// Backtrack to a position from [`Parser::checkpoint`].
+    pub fn restore(&mut self, checkpoint: usize) {
+        debug_assert!(checkpoint <= self.pos, "can only backtrack");
+        self.pos = checkpoint;
+    }
+
+    /// Run `parse` one nesting level deeper, failing once the limit is reached.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParserResult<T>) -> ParserResult<T> {
+        if self.depth >= self.max_depth {
+            return Err(ParseError::NestingTooDeep {
+                span: self.current().span().into(),
+            });
         }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
     }
 
     #[inline]
@@ -94,35 +336,146 @@ impl Parser {
         &self.current().0
     }
 
+    /// Step past the current token and return its span. Callers that need
+    /// the token itself read it with [`Parser::peek`] first.
     #[inline]
-    fn advance(&mut self) -> Spanned<Token> {
-        let tok = self.current().clone();
+    fn advance(&mut self) -> Span {
+        let span = self.current().span();
         if self.pos < self.len - 1 {
             self.pos += 1;
         }
-        tok
+        span
     }
 
-    fn expect(&mut self, expected: Token) -> Result<Spanned<Token>, ParseError> {
-        let (token, span) = self.current().clone();
-        if token == expected {
+    fn expect(&mut self, expected: Token) -> ParserResult<Span> {
+        if *self.peek() == expected {
             Ok(self.advance())
+        } else if self.at_eof() {
+            Err(self.unexpected_eof())
+        } else {
+            Err(ParseError::UnexpectedToken {
+                expected: vec![expected.kind()],
+                found: *self.peek(),
+                span: self.current().span().into(),
+            })
+        }
+    }
+
+    /// Consume the current token if it is any of `expected`, returning it so the
+    /// caller can tell which one matched.
+    fn expect_any(&mut self, expected: &[TokenKind]) -> ParserResult<Spanned<Token>> {
+        let token = *self.peek();
+        if expected.contains(&token.kind()) {
+            Ok((token, self.advance()))
+        } else if self.at_eof() {
+            Err(self.unexpected_eof())
         } else {
             Err(ParseError::UnexpectedToken {
-                expected,
+                expected: expected.to_vec(),
                 found: token,
-                span: span.into(),
+                span: self.current().span().into(),
             })
         }
     }
 
+    fn unexpected_eof(&self) -> ParseError {
+        ParseError::UnexpectedEOF {
+            span: self.current().span().into(),
+        }
+    }
+
+    /// The error for a missing `expected` closing what `opened` began at `open_span`.
+    fn expected_delimiter(&self, opened: Token, expected: Token, open_span: &Span) -> ParseError {
+        ParseError::ExpectedDelimiter {
+            opened,
+            expected,
+            open_span: open_span.clone().into(),
+            end_span: self.current().span().into(),
+        }
+    }
+
+    /// Report a missing `)` for the group opened at `open_span`. In recovering
+    /// mode the error is recorded and the group is closed after `last`, so the
+    /// rest of the declaration still parses.
+    fn close_missing_paren(&mut self, open_span: &Span, last: Span) -> ParserResult<Span> {
+        let error = self.expected_delimiter(Token::LParen, Token::RParen, open_span);
+        if !self.recovering {
+            return Err(error);
+        }
+        self.recovered.push(error);
+        Ok(last)
+    }
+
+    /// Parse a type, where `->` is right-associative.
     fn parse_type(&mut self) -> ParserResult<Spanned<Type>> {
-        let (token, span) = self.advance();
+        self.nested(Self::parse_arrow_type)
+    }
+
+    fn parse_arrow_type(&mut self) -> ParserResult<Spanned<Type>> {
+        let (param, param_span) = self.parse_list_type()?;
+        if *self.peek() != Token::Arrow {
+            return Ok((param, param_span));
+        }
+        self.advance();
+        let (ret, ret_span) = self.parse_type()?;
+        let span = param_span.merge(ret_span);
+        Ok((Type::Arrow(Box::new(param), Box::new(ret)), span))
+    }
+
+    /// Parse an atomic type followed by any number of postfix `list`s, so
+    /// `int list list` is a list of lists of ints.
+    ///
+    /// `list` is only special in this position and is not a reserved word, so it
+    /// can still be used as a name in expressions.
+    fn parse_list_type(&mut self) -> ParserResult<Spanned<Type>> {
+        let (mut ty, mut span) = self.parse_atom_type()?;
+        while matches!(self.peek(), Token::Ident(name) if name.as_str() == "list") {
+            let list_span = self.advance();
+            ty = Type::List(Box::new(ty));
+            span = span.merge(list_span);
+        }
+        Ok((ty, span))
+    }
+
+    fn parse_atom_type(&mut self) -> ParserResult<Spanned<Type>> {
+        if *self.peek() == Token::Eof {
+            return Err(self.unexpected_eof());
+        }
+        let token = *self.peek();
+        let span = self.advance();
         match token {
             Token::KwInt => Ok((Type::Int, span)),
+            Token::KwBool => Ok((Type::Bool, span)),
             Token::KwUnit => Ok((Type::Unit, span)),
             Token::KwReal => Ok((Type::Real, span)),
             Token::KwChar => Ok((Type::Char, span)),
+            Token::TypeVar(name) => Ok((Type::Var(Ident(name)), span)),
+            Token::LParen => {
+                if *self.peek() == Token::RParen {
+                    let r_span = self.advance();
+                    return Ok((Type::Unit, span.merge(r_span)));
+                }
+
+                let mut elems = vec![self.parse_type()?];
+                while *self.peek() == Token::Comma {
+                    self.advance();
+                    elems.push(self.parse_type()?);
+                }
+
+                match self.peek() {
+                    Token::RParen => {
+                        let r_span = self.advance();
+                        let span = span.merge(r_span);
+                        let ty = if elems.len() == 1 {
+                            elems.pop().unwrap().0
+                        } else {
+                            Type::Tuple(elems.into_iter().map(|(ty, _)| ty).collect())
+                        };
+                        Ok((ty, span))
+                    }
+                    _ => Err(self.expected_delimiter(Token::LParen, Token::RParen, &span)),
+                }
+            }
             _ => Err(ParseError::ExpectedType {
                 found: token,
                 span: span.into(),
@@ -131,176 +484,2002 @@ impl Parser {
     }
 
     fn parse_primary(&mut self) -> ParserResult<Spanned<Expr>> {
-        let (token, span) = self.advance();
+        if *self.peek() == Token::Eof {
+            return Err(self.unexpected_eof());
+        }
+        // Leave the offending token in place so error recovery can resynchronize on it.
+        if !self.at_arg_start() && !matches!(self.peek(), Token::KwIf | Token::KwCase) {
+            return Err(ParseError::ExpectedPrimary {
+                span: self.current().span().into(),
+            });
+        }
 
-        match token {
-            Token::Int(v) => Ok((Expr::Literal(Literal::Int(v)), span)),
-            Token::Real(x) => Ok((Expr::Literal(Literal::Real(x)), span)),
-            Token::Char(c) => Ok((Expr::Literal(Literal::Char(c)), span)),
-            Token::Ident(s) => Ok((Expr::Local(Ident(s)), span)),
+        let token = *self.peek();
+        let span = self.advance();
 
-            Token::LParen => {
-                if *self.peek() == Token::RParen {
-                    let (_, r_span) = self.advance();
-                    let span = span.merge(r_span);
-                    return Ok((Expr::Literal(Literal::Unit), span));
-                }
+        match token {
+            Token::KwIf => self.parse_if(span),
+            Token::KwCase => self.parse_case(span),
+            Token::KwLet => self.parse_let(span),
 
-                let (expr, expr_span) = self.parse_expr()?;
+            Token::LParen => self.parse_paren(span),
+            Token::LBracket => self.parse_list(span),
 
-                match self.peek() {
-                    Token::RParen => {
-                        let (_, r_span) = self.advance();
-                        let span = span.merge(expr_span).merge(r_span);
-                        Ok((expr, span))
-                    }
-                    _ => Err(ParseError::ExpectedDelimiter {
-                        opened: Token::LParen,
-                        expected: Token::RParen,
-                        open_span: span.into(),
-                        end_span: expr_span.into(),
-                    }),
-                }
-            }
+            token => Ok((Self::atom(token), span)),
+        }
+    }
 
-            _ => Err(ParseError::ExpectedPrimary { span: span.into() }),
+    /// The literal or variable spelled by a single token.
+    fn atom(token: Token) -> Expr {
+        match token {
+            Token::Int(v) => Expr::Literal(Literal::Int(v)),
+            Token::Real(x) => Expr::Literal(Literal::Real(x)),
+            Token::Char(c) => Expr::Literal(Literal::Char(c)),
+            Token::Bool(b) => Expr::Literal(Literal::Bool(b)),
+            Token::Ident(s) => Expr::Local(Ident(s)),
+            _ => unreachable!(),
         }
     }
 
-    fn parse_unary(&mut self) -> ParserResult<Spanned<Expr>> {
+    /// Parse the rest of `()`, `(e)`, a tuple `(e1, e2, ...)`, or a sequence
+    /// `(s1; s2; e)` after the opening parenthesis.
+    fn parse_paren(&mut self, span: Span) -> ParserResult<Spanned<Expr>> {
+        if *self.peek() == Token::RParen {
+            let r_span = self.advance();
+            let span = span.merge(r_span);
+            return Ok((Expr::Literal(Literal::Unit), span));
+        }
+        if *self.peek() == Token::KwWhile {
+            return self.parse_seq(span, None);
+        }
+
+        let first = self.parse_expr()?;
         match self.peek() {
-            Token::KwNot | Token::Tilde => {
-                let op = match self.peek() {
-                    Token::KwNot => UnaryOp::Not,
-                    Token::Tilde => UnaryOp::Neg,
-                    _ => unreachable!(),
-                };
-                let (_, op_span) = self.advance();
-                let (expr, expr_span) = self.parse_unary()?;
-                let span = op_span.clone().merge(expr_span.clone());
-                Ok((
-                    Expr::Unary {
-                        op: (op, op_span),
-                        expr: Box::new((expr, expr_span)),
-                    },
-                    span,
-                ))
-            }
-            Token::And => {
-                let (_, op_span) = self.advance();
-                let (op, op_span) = if *self.peek() == Token::KwMut {
-                    let (_, mut_span) = self.advance();
-                    (BorrowOp::RefMut, op_span.merge(mut_span))
-                } else {
-                    (BorrowOp::Ref, op_span)
-                };
-                let (expr, expr_span) = self.parse_unary()?;
-                let span = op_span.clone().merge(expr_span.clone());
-                Ok((
-                    Expr::Borrow {
-                        op: (op, op_span),
-                        expr: Box::new((expr, expr_span)),
-                    },
-                    span,
-                ))
+            // A single parenthesized expression is a grouping, not a 1-tuple.
+            Token::RParen => {
+                let r_span = self.advance();
+                Ok((first.0, span.merge(r_span)))
             }
-            _ => self.parse_primary(),
+            Token::Semi | Token::ColonEq => self.parse_seq(span, Some(first)),
+            _ => self.parse_tuple(span, first),
         }
     }
 
-    #[inline]
-    pub fn binary(
-        left: Spanned<Expr>,
-        op: BinaryOp,
-        op_span: Span,
-        right: Spanned<Expr>,
-    ) -> Spanned<Expr> {
-        let span = left.span().merge(right.span());
-        (
-            Expr::Binary {
-                left: Box::new(left),
-                right: Box::new(right),
-                op: (op, op_span),
-            },
-            span,
-        )
+    /// Parse the rest of a tuple after its first element.
+    fn parse_tuple(&mut self, span: Span, first: Spanned<Expr>) -> ParserResult<Spanned<Expr>> {
+        let mut elems = vec![first];
+        while *self.peek() == Token::Comma {
+            self.advance();
+            elems.push(self.parse_expr()?);
+        }
+
+        let r_span = match self.peek() {
+            Token::RParen => self.advance(),
+            _ => self.close_missing_paren(&span, elems.last().unwrap().span())?,
+        };
+        let span = span.merge(r_span);
+        // Recovering from a missing `)` can leave a lone element.
+        let expr = if elems.len() == 1 {
+            elems.pop().unwrap().0
+        } else {
+            Expr::Tuple(elems)
+        };
+        Ok((expr, span))
     }
 
-    fn parse_multiplicative(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        let mut left = self.parse_unary()?;
+    /// Parse the rest of a list literal `[e1, e2, ...]` after the opening
+    /// bracket. As with tuples, a trailing comma is not allowed.
+    fn parse_list(&mut self, span: Span) -> ParserResult<Spanned<Expr>> {
+        let mut elems = Vec::new();
+        if *self.peek() == Token::RBracket {
+            let r_span = self.advance();
+            return Ok((Expr::List(elems), span.merge(r_span)));
+        }
+
         loop {
-            let op = match self.peek() {
-                Token::Star => BinaryOp::Mul,
-                Token::KwDiv => BinaryOp::Div,
-                Token::KwMod => BinaryOp::Rem,
-                _ => break,
-            };
-            let (_, op_span) = self.advance();
-            let right = self.parse_unary()?;
-            left = Self::binary(left, op, op_span, right);
+            elems.push(self.parse_expr()?);
+            let (token, sep_span) = self.expect_any(&[TokenKind::Comma, TokenKind::RBracket])?;
+            if token == Token::RBracket {
+                return Ok((Expr::List(elems), span.merge(sep_span)));
+            }
         }
-        Ok(left)
     }
 
-    fn parse_additive(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        let mut left = self.parse_multiplicative()?;
-        loop {
-            let op = match self.peek() {
-                Token::Plus => BinaryOp::And,
-                Token::Minus => BinaryOp::Sub,
-                _ => break,
-            };
-            let (_, op_span) = self.advance();
-            let right = self.parse_multiplicative()?;
-            left = Self::binary(left, op, op_span, right);
+    /// Parse an element of a parenthesized sequence, which may be a `while` loop
+    /// or an assignment as well as an expression.
+    fn parse_seq_item(&mut self) -> ParserResult<SeqItem> {
+        if *self.peek() == Token::KwWhile {
+            return Ok(SeqItem::Stmt(Box::new(self.parse_while()?)));
         }
-        Ok(left)
+        let expr = self.parse_expr()?;
+        self.finish_seq_item(expr)
     }
 
-    fn parse_comparison(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        let mut left = self.parse_additive()?;
-        loop {
-            let op = match self.peek() {
-                Token::Gt => BinaryOp::Greater,
-                Token::GtEq => BinaryOp::GreaterEq,
-                Token::Less => BinaryOp::Less,
-                Token::LessEq => BinaryOp::LessEq,
-                Token::NotEq => BinaryOp::NotEq,
-                Token::Eq => BinaryOp::Eq,
-                _ => break,
-            };
-            let (_, op_span) = self.advance();
-            let right = self.parse_additive()?;
-            left = Self::binary(left, op, op_span, right);
+    /// Finish a sequence element that began with `expr`.
+    fn finish_seq_item(&mut self, expr: Spanned<Expr>) -> ParserResult<SeqItem> {
+        if *self.peek() != Token::ColonEq {
+            return Ok(SeqItem::Expr(expr));
         }
-        Ok(left)
+        let target = self.assign_target(expr)?;
+        let value = self.parse_expr()?;
+        Ok(SeqItem::Stmt(Box::new(Self::assign(target, value))))
     }
 
-    fn parse_and_op(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        let mut left = self.parse_comparison()?;
-        while let Token::AndAnd = self.peek() {
-            let (_, op_span) = self.advance();
-            let right = self.parse_comparison()?;
-            left = Self::binary(left, BinaryOp::And, op_span, right);
+    /// Parse the rest of `(<item>; <item>; ...; <expr>)`, starting from its
+    /// first item if that has already been parsed as an expression.
+    /// Every item but the last is run for its effect, and the last must be an
+    /// expression, which gives the value of the sequence.
+    fn parse_seq(
+        &mut self,
+        l_span: Span,
+        first: Option<Spanned<Expr>>,
+    ) -> ParserResult<Spanned<Expr>> {
+        let first = match first {
+            Some(expr) => self.finish_seq_item(expr),
+            None => self.parse_seq_item(),
+        }?;
+        let mut items = vec![first];
+        while *self.peek() == Token::Semi {
+            self.advance();
+            items.push(self.parse_seq_item()?);
         }
-        Ok(left)
+        self.finish_seq(l_span, items)
+    }
+
+    /// Build a sequence from its items, the last of which must be an expression.
+    fn finish_seq(&mut self, l_span: Span, mut items: Vec<SeqItem>) -> ParserResult<Spanned<Expr>> {
+        let Some(SeqItem::Expr(expr)) = items.pop() else {
+            // A trailing statement needs a `;` and a result expression after it.
+            return Err(ParseError::UnexpectedToken {
+                expected: vec![TokenKind::Semi],
+                found: *self.peek(),
+                span: self.current().span().into(),
+            });
+        };
+        let stmts = items.into_iter().map(SeqItem::into_stmt).collect();
+        let r_span = match self.peek() {
+            Token::RParen => self.advance(),
+            _ => self.close_missing_paren(&l_span, expr.span())?,
+        };
+        let span = l_span.merge(r_span);
+        Ok((
+            Expr::Seq {
+                stmts,
+                expr: Box::new(expr),
+            },
+            span,
+        ))
     }
 
-    fn parse_or_op(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        let mut left = self.parse_and_op()?;
-        while let Token::Or = self.peek() {
-            let (_, op_span) = self.advance();
-            let right = self.parse_and_op()?;
-            left = Self::binary(left, BinaryOp::Or, op_span, right);
+    /// Parse the remainder of `if <cond> then <expr> else <expr>` after `if`.
+    fn parse_if(&mut self, if_span: Span) -> ParserResult<Spanned<Expr>> {
+        let condition = self.parse_expr()?;
+        self.expect(Token::KwThen)?;
+        let then_expr = self.parse_expr()?;
+        self.expect(Token::KwElse)?;
+        let else_expr = self.parse_expr()?;
+        let span = if_span.merge(else_expr.span());
+        Ok((
+            Expr::If {
+                condition: Box::new(condition),
+                then_expr: Box::new(then_expr),
+                else_expr: Box::new(else_expr),
+            },
+            span,
+        ))
+    }
+
+    /// Parse the rest of `case <expr> of <arm> | <arm> ...` after `case`.
+    ///
+    /// As in SML, the body of an arm extends as far as possible, so a `case`
+    /// nested inside an arm needs parentheses to leave the later arms alone.
+    fn parse_case(&mut self, case_span: Span) -> ParserResult<Spanned<Expr>> {
+        let scrutinee = self.parse_expr()?;
+        self.expect(Token::KwOf)?;
+
+        let mut arms = vec![self.parse_arm()?];
+        while *self.peek() == Token::Bar {
+            self.advance();
+            arms.push(self.parse_arm()?);
         }
-        Ok(left)
+        // Neither can follow an expression, so the `|` before another arm is missing.
+        if matches!(self.peek(), Token::Underscore | Token::FatArrow) {
+            return Err(ParseError::UnexpectedToken {
+                expected: vec![TokenKind::Bar],
+                found: *self.peek(),
+                span: self.current().span().into(),
+            });
+        }
+
+        let span = case_span.merge(arms.last().unwrap().expr.span());
+        Ok((
+            Expr::Case {
+                scrutinee: Box::new(scrutinee),
+                arms,
+            },
+            span,
+        ))
     }
 
-    fn parse_expr(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        self.parse_or_op()
+    /// Parse `<pattern> => <expr>`.
+    fn parse_arm(&mut self) -> ParserResult<Arm> {
+        let pattern = self.parse_pattern()?;
+        self.expect(Token::FatArrow)?;
+        let expr = self.parse_expr()?;
+        Ok(Arm { pattern, expr })
     }
 
-    pub fn parse_code(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        self.parse_or_op()
+    /// Parse `_` or a literal, where a negative integer is written `~1`.
+    fn parse_pattern(&mut self) -> ParserResult<Spanned<Pattern>> {
+        let (token, span) = self.current().clone();
+        let pattern = match token {
+            Token::Underscore => Pattern::Wildcard,
+            Token::Int(v) => Pattern::Literal(Literal::Int(v)),
+            Token::Char(c) => Pattern::Literal(Literal::Char(c)),
+            Token::Bool(b) => Pattern::Literal(Literal::Bool(b)),
+            Token::Tilde => {
+                self.advance();
+                let Token::Int(v) = *self.peek() else {
+                    return Err(ParseError::ExpectedPattern {
+                        found: *self.peek(),
+                        span: self.current().span().into(),
+                    });
+                };
+                let int_span = self.advance();
+                return Ok((Pattern::Literal(Literal::Int(-v)), span.merge(int_span)));
+            }
+            Token::Eof => return Err(self.unexpected_eof()),
+            _ => {
+                return Err(ParseError::ExpectedPattern {
+                    found: token,
+                    span: span.into(),
+                });
+            }
+        };
+        self.advance();
+        Ok((pattern, span))
+    }
+
+    /// Parse the remainder of `let <stmts> in <expr> end` after `let`.
+    ///
+    /// As in SML, statements may be separated by `;`, but the separator is
+    /// optional and a trailing `;` before `in` is allowed.
+    fn parse_let(&mut self, let_span: Span) -> ParserResult<Spanned<Expr>> {
+        let mut stmts = Vec::new();
+        while !matches!(self.peek(), Token::KwIn | Token::KwEnd | Token::Eof) {
+            stmts.push(self.parse_stmt()?);
+            if *self.peek() == Token::Semi {
+                self.advance();
+            }
+        }
+
+        if *self.peek() != Token::KwIn {
+            return Err(self.expected_delimiter(Token::KwLet, Token::KwIn, &let_span));
+        }
+        self.advance();
+
+        let expr = self.parse_expr()?;
+
+        if *self.peek() != Token::KwEnd {
+            return Err(self.expected_delimiter(Token::KwLet, Token::KwEnd, &let_span));
+        }
+        let end_span = self.advance();
+
+        let span = let_span.merge(end_span);
+        Ok((
+            Expr::Let {
+                stmts,
+                expr: Box::new(expr),
+            },
+            span,
+        ))
+    }
+
+    fn parse_stmt(&mut self) -> ParserResult<Spanned<Stmt>> {
+        let (token, span) = self.current().clone();
+        match token {
+            Token::KwVal => self.parse_val().map(|val| map_spanned(val, Stmt::Val)),
+            Token::KwFun => self.parse_func().map(|func| map_spanned(func, Stmt::Fun)),
+            Token::KwWhile => self.parse_while(),
+            Token::Eof => Err(self.unexpected_eof()),
+            _ if self.at_arg_start()
+                || matches!(token, Token::Tilde | Token::KwNot | Token::And) =>
+            {
+                self.parse_assign()
+            }
+            _ => Err(ParseError::ExpectedStatement {
+                found: token,
+                span: span.into(),
+            }),
+        }
+    }
+
+    /// Parse `<ident> := <expr>`.
+    fn parse_assign(&mut self) -> ParserResult<Spanned<Stmt>> {
+        let target = self.parse_expr()?;
+        let target = self.assign_target(target)?;
+        let value = self.parse_expr()?;
+        Ok(Self::assign(target, value))
+    }
+
+    fn assign(target: Spanned<Ident>, value: Spanned<Expr>) -> Spanned<Stmt> {
+        let span = target.span().merge(value.span());
+        (Stmt::Assign { target, value }, span)
+    }
+
+    /// Check that `target` can be assigned to and consume the `:=` after it.
+    fn assign_target(
+        &mut self,
+        (target, target_span): Spanned<Expr>,
+    ) -> ParserResult<Spanned<Ident>> {
+        self.expect(Token::ColonEq)?;
+        let Expr::Local(ident) = target else {
+            return Err(ParseError::InvalidAssignTarget {
+                span: target_span.into(),
+            });
+        };
+        Ok((ident, target_span))
+    }
+
+    /// Parse `while <expr> do <stmt>`.
+    fn parse_while(&mut self) -> ParserResult<Spanned<Stmt>> {
+        let while_span = self.expect(Token::KwWhile)?;
+        let condition = self.parse_expr()?;
+        self.expect(Token::KwDo)?;
+        let body = self.nested(Self::parse_stmt)?;
+        let span = while_span.merge(body.span());
+        Ok((
+            Stmt::While {
+                condition,
+                body: Box::new(body),
+            },
+            span,
+        ))
+    }
+
+    /// Parse `val <ident> [: <type>] = <expr>`.
+    fn parse_val(&mut self) -> ParserResult<Spanned<Val>> {
+        let (val_span, name, ty) = self.parse_val_head()?;
+        let expr = self.parse_expr()?;
+        let span = val_span.merge(expr.span());
+        Ok((Val { name, ty, expr }, span))
+    }
+
+    /// Parse `val <ident> [: <type>] =`, up to the bound expression.
+    fn parse_val_head(&mut self) -> ParserResult<(Span, Spanned<Ident>, Option<Spanned<Type>>)> {
+        let val_span = self.expect(Token::KwVal)?;
+        let name = self.parse_ident()?;
+
+        let ty = if *self.peek() == Token::Colon {
+            self.advance();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        self.expect(Token::Eq)?;
+        Ok((val_span, name, ty))
+    }
+
+    fn parse_decl(&mut self) -> ParserResult<Spanned<Decl>> {
+        let (token, span) = self.current().clone();
+        match token {
+            Token::KwFun => self.parse_func_decl(),
+            Token::KwVal => self.parse_val().map(|val| map_spanned(val, Decl::Val)),
+            _ => Err(ParseError::ExpectedDecl {
+                found: token,
+                span: span.into(),
+            }),
+        }
+    }
+
+    /// Parse a function declaration, chaining `and <func>` clauses into a
+    /// mutually recursive group as in SML.
+    fn parse_func_decl(&mut self) -> ParserResult<Spanned<Decl>> {
+        let (func, mut span) = self.parse_func()?;
+        if *self.peek() != Token::KwAnd {
+            return Ok((Decl::Func(func), span));
+        }
+
+        let mut funcs = vec![func];
+        while *self.peek() == Token::KwAnd {
+            let and_span = self.advance();
+            let (func, func_span) = self.parse_func_clause(and_span)?;
+            funcs.push(func);
+            span = span.merge(func_span);
+        }
+        Ok((Decl::FuncGroup(funcs), span))
+    }
+
+    /// Parse `fun <ident> <params> [: <type>] = <expr>`.
+    fn parse_func(&mut self) -> ParserResult<Spanned<Func>> {
+        let fun_span = self.expect(Token::KwFun)?;
+        self.parse_func_clause(fun_span)
+    }
+
+    /// Parse the rest of a function after its leading `fun` or `and`.
+    fn parse_func_clause(&mut self, fun_span: Span) -> ParserResult<Spanned<Func>> {
+        let (name, params, ty) = self.parse_func_head()?;
+        let expr = self.parse_expr()?;
+        let span = fun_span.merge(expr.span());
+        Ok((
+            Func {
+                name,
+                params,
+                ty,
+                expr,
+            },
+            span,
+        ))
+    }
+
+    /// Parse `<ident> <params> [: <type>] =`, up to the function body.
+    fn parse_func_head(&mut self) -> ParserResult<FuncHead> {
+        let name = self.parse_ident()?;
+
+        let mut params = Vec::new();
+        while matches!(self.peek(), Token::Ident(_) | Token::LParen) {
+            params.push(self.parse_param()?);
+        }
+
+        let ty = if *self.peek() == Token::Colon {
+            self.advance();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        self.expect(Token::Eq)?;
+        Ok((name, params, ty))
+    }
+
+    /// Parse a parameter, either `x` or `(x : <type>)`.
+    fn parse_param(&mut self) -> ParserResult<Spanned<FuncParam>> {
+        if *self.peek() != Token::LParen {
+            return self
+                .parse_ident()
+                .map(|ident| map_spanned(ident, FuncParam::Ident));
+        }
+
+        let l_span = self.advance();
+        let param = map_spanned(self.parse_ident()?, FuncParam::Ident);
+        self.expect(Token::Colon)?;
+        let ty = self.parse_type()?;
+        let r_span = self.expect(Token::RParen)?;
+        Ok((
+            FuncParam::Typed {
+                param: Box::new(param),
+                ty,
+            },
+            l_span.merge(r_span),
+        ))
+    }
+
+    fn parse_ident(&mut self) -> ParserResult<Spanned<Ident>> {
+        let (token, span) = self.current().clone();
+        match token {
+            Token::Ident(s) => {
+                self.advance();
+                Ok((Ident(s), span))
+            }
+            Token::Eof => Err(self.unexpected_eof()),
+            _ if token.is_keyword() => Err(ParseError::ReservedKeywordAsIdent {
+                keyword: token,
+                span: span.into(),
+            }),
+            _ => Err(ParseError::ExpectedIdent {
+                found: token,
+                span: span.into(),
+            }),
+        }
+    }
+
+    /// Parse prefix `not`, `~` and borrows, which bind tighter than every binary
+    /// operator. This matches SML, where `not` is an ordinary function, so
+    /// `not a = b` means `(not a) = b`; write `not (a = b)` to negate a comparison.
+    fn parse_unary(&mut self) -> ParserResult<Spanned<Expr>> {
+        // Most operands have no prefix, so they skip the larger frame of
+        // `parse_prefixed` on the way down.
+        match self.peek() {
+            Token::KwNot | Token::Tilde | Token::And => self.parse_prefixed(),
+            _ => self.parse_primary(),
+        }
+    }
+
+    fn parse_prefixed(&mut self) -> ParserResult<Spanned<Expr>> {
+        match self.peek() {
+            Token::KwNot | Token::Tilde => {
+                let op = match self.peek() {
+                    Token::KwNot => UnaryOp::Not,
+                    Token::Tilde => UnaryOp::Neg,
+                    _ => unreachable!(),
+                };
+                let op_span = self.advance();
+                let (expr, expr_span) = self.nested(Self::parse_unary)?;
+                let span = op_span.clone().merge(expr_span.clone());
+                Ok((
+                    Expr::Unary {
+                        op: (op, op_span),
+                        expr: Box::new((expr, expr_span)),
+                    },
+                    span,
+                ))
+            }
+            Token::And => {
+                let op_span = self.advance();
+                let (op, op_span) = if *self.peek() == Token::KwMut {
+                    let mut_span = self.advance();
+                    (BorrowOp::RefMut, op_span.merge(mut_span))
+                } else {
+                    (BorrowOp::Ref, op_span)
+                };
+                let (expr, expr_span) = self.nested(Self::parse_unary)?;
+                let span = op_span.clone().merge(expr_span.clone());
+                Ok((
+                    Expr::Borrow {
+                        op: (op, op_span),
+                        expr: Box::new((expr, expr_span)),
+                    },
+                    span,
+                ))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline]
+    pub fn binary(
+        left: Spanned<Expr>,
+        op: BinaryOp,
+        op_span: Span,
+        right: Spanned<Expr>,
+    ) -> Spanned<Expr> {
+        let span = left.span().merge(right.span());
+        (
+            Expr::Binary {
+                left: Box::new(left),
+                right: Box::new(right),
+                op: (op, op_span),
+            },
+            span,
+        )
+    }
+
+    /// Whether the current token can begin an argument of a function application.
+    fn at_arg_start(&self) -> bool {
+        matches!(
+            self.peek(),
+            Token::Int(_)
+                | Token::Real(_)
+                | Token::Char(_)
+                | Token::Bool(_)
+                | Token::Ident(_)
+                | Token::LParen
+                | Token::LBracket
+                | Token::KwLet
+        )
+    }
+
+    /// Parse the arguments applied to `callee`, if any.
+    fn parse_args(&mut self, mut callee: Spanned<Expr>) -> ParserResult<Spanned<Expr>> {
+        while self.at_arg_start() {
+            let arg = self.parse_primary()?;
+            let span = callee.span().merge(arg.span());
+            callee = (
+                Expr::Apply {
+                    callee: Box::new(callee),
+                    arg: Box::new(arg),
+                },
+                span,
+            );
+        }
+        Ok(callee)
+    }
+
+    /// The binary operator spelled by `token`, if any.
+    fn binary_op(token: &Token) -> Option<BinaryOp> {
+        let op = match token {
+            Token::Or => BinaryOp::LogicalOr,
+            Token::AndAnd => BinaryOp::LogicalAnd,
+            Token::Gt => BinaryOp::Greater,
+            Token::GtEq => BinaryOp::GreaterEq,
+            Token::Less => BinaryOp::Less,
+            Token::LessEq => BinaryOp::LessEq,
+            Token::NotEq => BinaryOp::NotEq,
+            Token::Eq => BinaryOp::Eq,
+            Token::Cons => BinaryOp::Cons,
+            Token::Plus => BinaryOp::Add,
+            Token::Minus => BinaryOp::Sub,
+            Token::Star => BinaryOp::Mul,
+            Token::KwDiv | Token::Slash => BinaryOp::Div,
+            Token::KwMod | Token::Percent => BinaryOp::Rem,
+            Token::Caret => BinaryOp::Pow,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    /// Parse operators binding at least as tightly as `min_prec` by precedence
+    /// climbing over [`precedence`]. Application and prefix operators bind
+    /// tighter than any of them, so `~2 ^ 3` is `(~2) ^ 3`.
+    ///
+    /// One loop handles every level, rather than a function per level, which
+    /// keeps the stack used by each nested parenthesis or `let` small. Only a
+    /// right-associative chain recurses once per operator, so that recursion
+    /// counts against the nesting limit.
+    fn parse_binary(&mut self, min_prec: u8) -> ParserResult<Spanned<Expr>> {
+        let mut left = self.parse_unary()?;
+        if self.at_arg_start() {
+            left = self.parse_args(left)?;
+        }
+        if Self::binary_op(self.peek()).is_none() {
+            return Ok(left);
+        }
+        self.parse_operators(left, min_prec)
+    }
+
+    /// Parse the operators and operands following `left`, down to `min_prec`.
+    fn parse_operators(
+        &mut self,
+        mut left: Spanned<Expr>,
+        min_prec: u8,
+    ) -> ParserResult<Spanned<Expr>> {
+        while let Some(op) = Self::binary_op(self.peek()) {
+            let (prec, assoc) = precedence(op);
+            if prec < min_prec {
+                break;
+            }
+            let op_span = self.advance();
+            let right = match assoc {
+                Associativity::Right => self.nested(|p| p.parse_binary(prec))?,
+                Associativity::Left | Associativity::None => self.parse_binary(prec + 1)?,
+            };
+            left = Self::binary(left, op, op_span, right);
+
+            // Comparisons are non-associative: `a < b < c` is rejected rather
+            // than parsed as `(a < b) < c`.
+            if assoc == Associativity::None
+                && Self::binary_op(self.peek()).is_some_and(|next| precedence(next).0 == prec)
+            {
+                let span = left.span().merge(self.current().span());
+                return Err(ParseError::ChainedComparison { span: span.into() });
+            }
+        }
+        Ok(left)
+    }
+
+    /// Parse an expression, optionally ascribed a type with a trailing `: ty`.
+    /// Ascription binds looser than every operator, so `1 + 2 : int` ascribes
+    /// the whole sum.
+    ///
+    /// Every nested expression is parsed through here, so this is where the
+    /// nesting limit counts parentheses, lists, `let`, `if` and `case`.
+    fn parse_expr(&mut self) -> ParserResult<Spanned<Expr>> {
+        self.nested(Self::parse_ascription)
+    }
+
+    fn parse_ascription(&mut self) -> ParserResult<Spanned<Expr>> {
+        let expr = self.parse_binary(0)?;
+        if *self.peek() != Token::Colon {
+            return Ok(expr);
+        }
+        self.finish_ascription(expr)
+    }
+
+    fn finish_ascription(&mut self, expr: Spanned<Expr>) -> ParserResult<Spanned<Expr>> {
+        self.advance();
+        let ty = self.parse_type()?;
+        let span = expr.1.clone().merge(ty.1.clone());
+        Ok((
+            Expr::Ascription {
+                expr: Box::new(expr),
+                ty,
+            },
+            span,
+        ))
+    }
+
+    pub fn parse_code(&mut self) -> Result<Spanned<Expr>, ParseError> {
+        let expr = self.parse_expr()?;
+        self.expect(Token::Eof)?;
+        Ok(expr)
+    }
+
+    /// Parse a lone type, requiring all input to be consumed.
+    pub fn parse_type_code(&mut self) -> ParserResult<Spanned<Type>> {
+        let ty = self.parse_type()?;
+        self.expect(Token::Eof)?;
+        Ok(ty)
+    }
+
+    /// Parse one declaration or expression, requiring all input to be consumed.
+    pub fn parse_repl_item(&mut self) -> ParserResult<ReplItem> {
+        let item = match self.peek() {
+            Token::KwFun | Token::KwVal => ReplItem::Decl(self.parse_decl()?),
+            _ => ReplItem::Expr(self.parse_expr()?),
+        };
+        self.expect(Token::Eof)?;
+        Ok(item)
+    }
+
+    /// Parse top-level declarations up to the end of input. An empty program is
+    /// valid and yields no declarations.
+    pub fn parse_program(&mut self) -> ParserResult<Vec<Spanned<Decl>>> {
+        let mut decls = Vec::new();
+        while *self.peek() != Token::Eof {
+            decls.push(self.parse_decl()?);
+        }
+        Ok(decls)
+    }
+
+    /// Parse a program, collecting every error instead of stopping at the first.
+    ///
+    /// After an error the parser skips ahead to the next `fun` or `val` and resumes there.
+    /// A missing `)` is instead reported and treated as if it were present.
+    pub fn parse_program_recovering(&mut self) -> (Vec<Spanned<Decl>>, Vec<ParseError>) {
+        let mut decls = Vec::new();
+        let mut errors = Vec::new();
+        self.recovering = true;
+        while *self.peek() != Token::Eof {
+            let start = self.pos;
+            let result = self.parse_decl();
+            errors.append(&mut self.recovered);
+            match result {
+                Ok(decl) => decls.push(decl),
+                Err(error) => {
+                    errors.push(error);
+                    self.synchronize(start);
+                }
+            }
+        }
+        self.recovering = false;
+        (decls, errors)
+    }
+
+    /// Skip tokens until the start of the next declaration, always making progress.
+    fn synchronize(&mut self, start: usize) {
+        if self.pos == start {
+            self.advance();
+        }
+        while !matches!(self.peek(), Token::KwFun | Token::KwVal | Token::Eof) {
+            self.advance();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lexer::{Lexer, LexerConfig},
+        span::SourceId,
+    };
+
+    fn parse(input: &str) -> Spanned<Expr> {
+        let tokens = Lexer::new(SourceId::default(), input, LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        Parser::new(tokens).parse_code().unwrap()
+    }
+
+    #[test]
+    fn test_remaining_and_at_eof() {
+        let tokens = Lexer::new(SourceId::default(), "f x val y", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let mut parser = Parser::new(tokens);
+        assert_eq!(parser.remaining().len(), 5);
+        assert!(!parser.at_eof());
+
+        parser.parse_expr().unwrap();
+        let kinds: Vec<_> = parser.remaining().iter().map(|(t, _)| t.kind()).collect();
+        assert_eq!(kinds, [TokenKind::KwVal, TokenKind::Ident, TokenKind::Eof]);
+        assert!(!parser.at_eof());
+
+        parser.advance();
+        parser.advance();
+        assert!(parser.at_eof());
+        assert_eq!(parser.remaining().len(), 1);
+    }
+
+    #[test]
+    fn test_checkpoint_restore() {
+        let tokens = Lexer::new(SourceId::default(), "(x, y)", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let mut parser = Parser::new(tokens);
+
+        let checkpoint = parser.checkpoint();
+        assert!(parser.parse_type().is_err());
+        assert_ne!(parser.checkpoint(), checkpoint);
+
+        parser.restore(checkpoint);
+        let (expr, span) = parser.parse_expr().unwrap();
+        assert!(matches!(expr, Expr::Tuple(_)));
+        assert_eq!(span.range, 0..6);
+        assert!(parser.at_eof());
+    }
+
+    #[test]
+    fn test_addition() {
+        let (expr, _) = parse("1 + 2");
+        let Expr::Binary { left, op, right } = expr else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(op.0, BinaryOp::Add);
+        assert_eq!(left.0, Expr::Literal(Literal::Int(1)));
+        assert_eq!(right.0, Expr::Literal(Literal::Int(2)));
+    }
+
+    #[test]
+    fn test_literal_display_round_trip() {
+        for input in ["42", "2.5", "'a'", "()"] {
+            let (expr, _) = parse(input);
+            let Expr::Literal(literal) = expr else {
+                panic!("expected literal for {input}");
+            };
+            assert_eq!(literal.to_string(), input);
+        }
+    }
+
+    #[test]
+    fn test_if_expression() {
+        let (expr, span) = parse("if x then 1 else 2");
+        assert_eq!(span.range, 0..18);
+        let Expr::If {
+            condition,
+            then_expr,
+            else_expr,
+        } = expr
+        else {
+            panic!("expected if expression");
+        };
+        assert!(matches!(condition.0, Expr::Local(_)));
+        assert_eq!(then_expr.0, Expr::Literal(Literal::Int(1)));
+        assert_eq!(else_expr.0, Expr::Literal(Literal::Int(2)));
+    }
+
+    #[test]
+    fn test_nested_if_expression() {
+        let (expr, _) = parse("if a then if b then 1 else 2 else if c then 3 else 4");
+        let Expr::If {
+            then_expr,
+            else_expr,
+            ..
+        } = expr
+        else {
+            panic!("expected if expression");
+        };
+        assert!(matches!(then_expr.0, Expr::If { .. }));
+        assert!(matches!(else_expr.0, Expr::If { .. }));
+    }
+
+    #[test]
+    fn test_if_missing_then() {
+        let tokens = Lexer::new(SourceId::default(), "if x else 2", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::UnexpectedToken {
+                ref expected,
+                found: Token::KwElse,
+                ..
+            } if *expected == [TokenKind::KwThen]
+        ));
+    }
+
+    #[test]
+    fn test_if_missing_else() {
+        let tokens = Lexer::new(SourceId::default(), "if x then 1", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        let ParseError::UnexpectedEOF { span } = err else {
+            panic!("expected end of input error, got {err:?}");
+        };
+        assert_eq!(span, SourceSpan::new(11.into(), 0));
+    }
+
+    #[test]
+    fn test_let_expression() {
+        let (expr, span) = parse("let in x + 1 end");
+        assert_eq!(span.range, 0..16);
+        let Expr::Let { stmts, expr } = expr else {
+            panic!("expected let expression");
+        };
+        assert!(stmts.is_empty());
+        assert!(matches!(expr.0, Expr::Binary { .. }));
+    }
+
+    #[test]
+    fn test_let_span_covers_let_through_end() {
+        let input = "  let val x = 1 while x < 3 do x := x + 1 in x end  ";
+        let (_, span) = parse(input);
+        assert_eq!(
+            &input[span.range],
+            "let val x = 1 while x < 3 do x := x + 1 in x end"
+        );
+    }
+
+    #[test]
+    fn test_let_semicolon_separators() {
+        let (expr, span) = parse("let val x = 1; val y = 2 in x end");
+        assert_eq!(span.range, 0..33);
+        let Expr::Let { stmts, expr } = expr else {
+            panic!("expected let expression");
+        };
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(stmts[0].1.range, 4..13);
+        assert_eq!(stmts[1].1.range, 15..24);
+        assert!(matches!(expr.0, Expr::Local(_)));
+
+        let with_trailing = parse("let val x = 1; val y = 2; in x end");
+        assert!(
+            with_trailing
+                .0
+                .structurally_eq(&parse("let val x = 1 val y = 2 in x end").0)
+        );
+    }
+
+    #[test]
+    fn test_let_empty_statement() {
+        let tokens = Lexer::new(
+            SourceId::default(),
+            "let val x = 1;; in x end",
+            LexerConfig::default(),
+        )
+        .tokenize()
+        .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::ExpectedStatement {
+                found: Token::Semi,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_let_local_fun() {
+        let (expr, _) = parse("let fun sq x = x * x in sq 3 end");
+        let Expr::Let { stmts, expr } = expr else {
+            panic!("expected let expression");
+        };
+        let (Stmt::Fun(func), span) = &stmts[0] else {
+            panic!("expected local function");
+        };
+        assert_eq!(func.name.0, Ident::from("sq"));
+        assert_eq!(func.params.len(), 1);
+        assert_eq!(span.range, 4..20);
+        assert_eq!(expr.0.to_string(), "(sq 3)");
+
+        let (expr, _) = parse("let fun f (x : int) : int = x + 1; val y = f 2 in y end");
+        assert_eq!(
+            expr.to_string(),
+            "let fun f (x : int) : int = (x + 1); val y = (f 2) in y end"
+        );
+    }
+
+    #[test]
+    fn test_sequence_expression() {
+        let (expr, span) = parse("(x := 1; f x; x)");
+        assert_eq!(span.range, 0..16);
+        let Expr::Seq { stmts, expr } = expr else {
+            panic!("expected sequence");
+        };
+        assert_eq!(stmts.len(), 2);
+        assert!(matches!(stmts[0].0, Stmt::Assign { .. }));
+        assert_eq!(stmts[0].1.range, 1..7);
+        assert!(matches!(stmts[1].0, Stmt::Expr(_)));
+        assert_eq!(expr.0, Expr::Local(Ident::from("x")));
+
+        assert!(matches!(parse("(1, 2)").0, Expr::Tuple(_)));
+        assert_eq!(
+            parse("(while x < 3 do x := x + 1; x)").0.to_string(),
+            "(while (x < 3) do x := (x + 1); x)"
+        );
+    }
+
+    #[test]
+    fn test_sequence_needs_result() {
+        for input in ["(x := 1)", "(1; x := 2)"] {
+            let tokens = Lexer::new(SourceId::default(), input, LexerConfig::default())
+                .tokenize()
+                .unwrap();
+            let err = Parser::new(tokens).parse_code().unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    ParseError::UnexpectedToken {
+                        ref expected,
+                        found: Token::RParen,
+                        ..
+                    } if *expected == [TokenKind::Semi]
+                ),
+                "{input}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_let_missing_in() {
+        let tokens = Lexer::new(SourceId::default(), "let end", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::ExpectedDelimiter {
+                expected: Token::KwIn,
+                opened: Token::KwLet,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_let_missing_end() {
+        let tokens = Lexer::new(SourceId::default(), "let in 1", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::ExpectedDelimiter {
+                expected: Token::KwEnd,
+                opened: Token::KwLet,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_val_binding() {
+        let (expr, _) = parse("let val x = 1 val y : int = x in y end");
+        let Expr::Let { stmts, .. } = expr else {
+            panic!("expected let expression");
+        };
+        assert_eq!(stmts.len(), 2);
+
+        let Stmt::Val(x) = &stmts[0].0 else {
+            panic!("expected val binding");
+        };
+        assert_eq!(x.name.0.to_string(), "x");
+        assert_eq!(x.ty, None);
+        assert_eq!(x.expr.0, Expr::Literal(Literal::Int(1)));
+        assert_eq!(stmts[0].1.range, 4..13);
+
+        let Stmt::Val(y) = &stmts[1].0 else {
+            panic!("expected val binding");
+        };
+        assert_eq!(y.name.0.to_string(), "y");
+        assert_eq!(y.ty, Some((Type::Int, Span::new(0, 22..25))));
+    }
+
+    #[test]
+    fn test_val_missing_eq() {
+        let tokens = Lexer::new(
+            SourceId::default(),
+            "let val x 1 in x end",
+            LexerConfig::default(),
+        )
+        .tokenize()
+        .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::UnexpectedToken {
+                ref expected,
+                found: Token::Int(1),
+                ..
+            } if *expected == [TokenKind::Eq]
+        ));
+    }
+
+    #[test]
+    fn test_curried_application() {
+        let (expr, span) = parse("f x y");
+        assert_eq!(span.range, 0..5);
+        let Expr::Apply { callee, arg } = expr else {
+            panic!("expected application");
+        };
+        assert!(matches!(arg.0, Expr::Local(ref id) if id.to_string() == "y"));
+        let Expr::Apply { callee, arg } = callee.0 else {
+            panic!("expected curried application");
+        };
+        assert!(matches!(callee.0, Expr::Local(ref id) if id.to_string() == "f"));
+        assert!(matches!(arg.0, Expr::Local(ref id) if id.to_string() == "x"));
+    }
+
+    #[test]
+    fn test_application_spans() {
+        let (_, span) = parse("f x");
+        assert_eq!(span.range, 0..3);
+
+        let (expr, span) = parse("f  x (y, z)");
+        assert_eq!(span.range, 0..11);
+        let Expr::Apply { callee, arg } = expr else {
+            panic!("expected application");
+        };
+        assert_eq!(callee.1.range, 0..4);
+        assert_eq!(arg.1.range, 5..11);
+        let Expr::Apply { callee, arg } = callee.0 else {
+            panic!("expected curried application");
+        };
+        assert_eq!((callee.1.range, arg.1.range), (0..1, 3..4));
+    }
+
+    #[test]
+    fn test_application_binds_tighter_than_binary() {
+        let (expr, _) = parse("f x + 1");
+        let Expr::Binary { left, op, .. } = expr else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(op.0, BinaryOp::Add);
+        assert!(matches!(left.0, Expr::Apply { .. }));
+
+        let (expr, _) = parse("f (x + 1)");
+        let Expr::Apply { arg, .. } = expr else {
+            panic!("expected application");
+        };
+        assert!(matches!(arg.0, Expr::Binary { .. }));
+    }
+
+    fn parse_decls(input: &str) -> Vec<Spanned<Decl>> {
+        let tokens = Lexer::new(SourceId::default(), input, LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        Parser::new(tokens).parse_program().unwrap()
+    }
+
+    #[test]
+    fn test_zero_param_function() {
+        let decls = parse_decls("fun answer = 42");
+        assert_eq!(decls.len(), 1);
+        assert_eq!(decls[0].1.range, 0..15);
+        let Decl::Func(func) = &decls[0].0 else {
+            panic!("expected function declaration");
+        };
+        assert_eq!(func.name.0.to_string(), "answer");
+        assert!(func.params.is_empty());
+        assert_eq!(func.ty, None);
+        assert_eq!(func.expr.0, Expr::Literal(Literal::Int(42)));
+    }
+
+    #[test]
+    fn test_mutually_recursive_functions() {
+        let src = "fun even n = if n = 0 then true else odd (n - 1)
+                   and odd n = if n = 0 then false else even (n - 1)
+                   val x = even 10";
+        let decls = parse_decls(src);
+        assert_eq!(decls.len(), 2);
+        let Decl::FuncGroup(funcs) = &decls[0].0 else {
+            panic!("expected function group");
+        };
+        let names: Vec<_> = funcs.iter().map(|func| func.name.0.to_string()).collect();
+        assert_eq!(names, ["even", "odd"]);
+        assert_eq!(decls[0].1.range, 0..src.rfind("1)").unwrap() + 2);
+        assert_eq!(
+            decls[0].0.to_string(),
+            "fun even n = (if (n = 0) then true else (odd (n - 1))) \
+             and odd n = (if (n = 0) then false else (even (n - 1)))"
+        );
+
+        // A lone function is not a group, and `&` is still a borrow.
+        assert!(matches!(parse_decls("fun f x = &x")[0].0, Decl::Func(_)));
+    }
+
+    #[test]
+    fn test_multi_param_function() {
+        let decls = parse_decls("fun add x (y : int) = x + y val z = add 1 2");
+        assert_eq!(decls.len(), 2);
+        let Decl::Func(func) = &decls[0].0 else {
+            panic!("expected function declaration");
+        };
+        assert_eq!(func.params.len(), 2);
+        assert!(matches!(&func.params[0].0, FuncParam::Ident(id) if id.to_string() == "x"));
+        assert!(matches!(
+            &func.params[1].0,
+            FuncParam::Typed {
+                ty: (Type::Int, _),
+                ..
+            }
+        ));
+        assert_eq!(func.params[1].1.range, 10..19);
+        assert!(matches!(decls[1].0, Decl::Val(_)));
+    }
+
+    #[test]
+    fn test_function_return_type() {
+        let decls = parse_decls("fun double x : int = x * 2");
+        let Decl::Func(func) = &decls[0].0 else {
+            panic!("expected function declaration");
+        };
+        assert_eq!(func.ty.as_ref().map(|ty| &ty.0), Some(&Type::Int));
+    }
+
+    #[test]
+    fn test_nested_while() {
+        let (expr, _) = parse("let while x do while y do val z = 1 in z end");
+        let Expr::Let { stmts, .. } = expr else {
+            panic!("expected let expression");
+        };
+        assert_eq!(stmts.len(), 1);
+        assert_eq!(stmts[0].1.range, 4..35);
+        let Stmt::While { condition, body } = &stmts[0].0 else {
+            panic!("expected while statement");
+        };
+        assert!(matches!(&condition.0, Expr::Local(id) if id.to_string() == "x"));
+        let Stmt::While { body, .. } = &body.0 else {
+            panic!("expected nested while statement");
+        };
+        assert!(matches!(body.0, Stmt::Val(_)));
+    }
+
+    #[test]
+    fn test_while_missing_do() {
+        let tokens = Lexer::new(
+            SourceId::default(),
+            "let while x val y = 1 in y end",
+            LexerConfig::default(),
+        )
+        .tokenize()
+        .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::UnexpectedToken {
+                ref expected,
+                found: Token::KwVal,
+                ..
+            } if *expected == [TokenKind::KwDo]
+        ));
+    }
+
+    #[test]
+    fn test_assignment() {
+        let (expr, _) = parse("let while x do y := 1 in y end");
+        let Expr::Let { stmts, .. } = expr else {
+            panic!("expected let expression");
+        };
+        let Stmt::While { body, .. } = &stmts[0].0 else {
+            panic!("expected while statement");
+        };
+        assert_eq!(body.1.range, 15..21);
+        let Stmt::Assign { target, value } = &body.0 else {
+            panic!("expected assignment");
+        };
+        assert_eq!(target.0.to_string(), "y");
+        assert_eq!(target.1.range, 15..16);
+        assert_eq!(value.0, Expr::Literal(Literal::Int(1)));
+    }
+
+    #[test]
+    fn test_invalid_assign_target() {
+        let tokens = Lexer::new(
+            SourceId::default(),
+            "let 1 := 2 in 1 end",
+            LexerConfig::default(),
+        )
+        .tokenize()
+        .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        assert!(matches!(err, ParseError::InvalidAssignTarget { .. }));
+    }
+
+    #[test]
+    fn test_tuple_expressions() {
+        let (expr, span) = parse("(1, x)");
+        assert_eq!(span.range, 0..6);
+        let Expr::Tuple(elems) = expr else {
+            panic!("expected tuple");
+        };
+        assert_eq!(elems.len(), 2);
+        assert_eq!(elems[0].0, Expr::Literal(Literal::Int(1)));
+
+        let (expr, _) = parse("(1, 2 + 3, (4, 5))");
+        let Expr::Tuple(elems) = expr else {
+            panic!("expected tuple");
+        };
+        assert_eq!(elems.len(), 3);
+        assert!(matches!(elems[1].0, Expr::Binary { .. }));
+        assert!(matches!(&elems[2].0, Expr::Tuple(inner) if inner.len() == 2));
+    }
+
+    #[test]
+    fn test_list_expressions() {
+        let (expr, span) = parse("[]");
+        assert_eq!(expr, Expr::List(vec![]));
+        assert_eq!(span.range, 0..2);
+
+        let (expr, _) = parse("[1]");
+        let Expr::List(elems) = expr else {
+            panic!("expected list");
+        };
+        assert_eq!(elems.len(), 1);
+        assert_eq!(elems[0].0, Expr::Literal(Literal::Int(1)));
+
+        let (expr, span) = parse("[1, 2, 3]");
+        assert_eq!(span.range, 0..9);
+        assert_eq!(expr.to_string(), "[1, 2, 3]");
+
+        assert_eq!(parse("f [x] :: []").0.to_string(), "((f [x]) :: [])");
+    }
+
+    #[test]
+    fn test_expect_any() {
+        let tokens = Lexer::new(SourceId::default(), ", ] )", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let mut parser = Parser::new(tokens);
+        let kinds = [TokenKind::RBracket, TokenKind::Comma];
+
+        let (token, span) = parser.expect_any(&kinds).unwrap();
+        assert_eq!((token, span.range), (Token::Comma, 0..1));
+        assert_eq!(parser.expect_any(&kinds).unwrap().0, Token::RBracket);
+
+        let err = parser.expect_any(&kinds).unwrap_err();
+        let ParseError::UnexpectedToken {
+            expected, found, ..
+        } = err
+        else {
+            panic!("expected unexpected-token error");
+        };
+        assert_eq!(expected, kinds);
+        assert_eq!(found, Token::RParen);
+
+        parser.advance();
+        let err = parser.expect_any(&kinds).unwrap_err();
+        assert!(matches!(err, ParseError::UnexpectedEOF { .. }));
+    }
+
+    #[test]
+    fn test_unterminated_list() {
+        let tokens = Lexer::new(SourceId::default(), "[1, 2", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        assert!(matches!(err, ParseError::UnexpectedEOF { .. }));
+
+        let tokens = Lexer::new(SourceId::default(), "[1, 2,]", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        assert!(matches!(err, ParseError::ExpectedPrimary { .. }));
+    }
+
+    #[test]
+    fn test_type_ascription() {
+        let (expr, span) = parse("(x : int)");
+        assert_eq!(span.range, 0..9);
+        let Expr::Ascription { expr, ty } = expr else {
+            panic!("expected ascription");
+        };
+        assert_eq!(expr.0, Expr::Local(Ident::from("x")));
+        assert_eq!(ty, (Type::Int, Span::new(0, 5..8)));
+
+        let (expr, span) = parse("1 + 2 : int");
+        assert_eq!(span.range, 0..11);
+        assert_eq!(expr.to_string(), "((1 + 2) : int)");
+
+        assert_eq!(
+            parse("(f x : int -> int, y)").0.to_string(),
+            "(((f x) : int -> int), y)"
+        );
+        assert_eq!(
+            parse("let val x : int = 1 : int in x end").0.to_string(),
+            "let val x : int = (1 : int) in x end"
+        );
+    }
+
+    #[test]
+    fn test_case_expression() {
+        let (expr, span) = parse("case n of 0 => 1 | ~1 => 2 | _ => n * 2");
+        assert_eq!(span.range, 0..39);
+        assert_eq!(
+            expr.to_string(),
+            "(case n of 0 => 1 | ~1 => 2 | _ => (n * 2))"
+        );
+        let Expr::Case { arms, .. } = expr else {
+            panic!("expected case expression");
+        };
+        let patterns: Vec<_> = arms.iter().map(|arm| arm.pattern.0.clone()).collect();
+        assert_eq!(
+            patterns,
+            [
+                Pattern::Literal(Literal::Int(0)),
+                Pattern::Literal(Literal::Int(-1)),
+                Pattern::Wildcard,
+            ]
+        );
+        assert_eq!(arms[1].pattern.1.range, 19..21);
+
+        assert_eq!(
+            parse("f (case c of 'a' => true | _ => false) 1")
+                .0
+                .to_string(),
+            "((f (case c of 'a' => true | _ => false)) 1)"
+        );
+    }
+
+    #[test]
+    fn test_case_errors() {
+        let parse_err = |input| {
+            let tokens = Lexer::new(SourceId::default(), input, LexerConfig::default())
+                .tokenize()
+                .unwrap();
+            Parser::new(tokens).parse_code().unwrap_err()
+        };
+
+        let err = parse_err("case n of 0 => 1 _ => 2");
+        assert_eq!(err.to_string(), "expected `|`, found `_`");
+        let err = parse_err("case n of 0 => 1 | 2 3");
+        assert_eq!(err.to_string(), "expected `=>`, found `3`");
+        assert!(matches!(
+            parse_err("case n of x => 1"),
+            ParseError::ExpectedPattern { .. }
+        ));
+        assert!(matches!(
+            parse_err("case n 0 => 1"),
+            ParseError::UnexpectedToken { .. }
+        ));
+    }
+
+    #[test]
+    fn test_grouping_and_unit() {
+        let (expr, span) = parse("(1)");
+        assert_eq!(expr, Expr::Literal(Literal::Int(1)));
+        assert_eq!(span.range, 0..3);
+
+        let (expr, _) = parse("()");
+        assert_eq!(expr, Expr::Literal(Literal::Unit));
+    }
+
+    #[test]
+    fn test_tuple_types() {
+        let decls = parse_decls("val p : (int, (char, real)) = (1, ('a', 2.0))");
+        let Decl::Val(val) = &decls[0].0 else {
+            panic!("expected val declaration");
+        };
+        let (ty, _) = val.ty.as_ref().unwrap();
+        assert_eq!(
+            *ty,
+            Type::Tuple(vec![Type::Int, Type::Tuple(vec![Type::Char, Type::Real])])
+        );
+        assert_eq!(ty.to_string(), "(int, (char, real))");
+    }
+
+    #[test]
+    fn test_bool_condition() {
+        let (expr, _) = parse("if true then 1 else 2");
+        let Expr::If { condition, .. } = expr else {
+            panic!("expected if expression");
+        };
+        assert_eq!(condition.0, Expr::Literal(Literal::Bool(true)));
+        assert_eq!(condition.1.range, 3..7);
+    }
+
+    fn parse_type_str(input: &str) -> Spanned<Type> {
+        let tokens = Lexer::new(SourceId::default(), input, LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        Parser::new(tokens).parse_type().unwrap()
+    }
+
+    #[test]
+    fn test_arrow_types() {
+        let (ty, span) = parse_type_str("int -> bool -> real");
+        assert_eq!(span.range, 0..19);
+        assert_eq!(
+            ty,
+            Type::Arrow(
+                Box::new(Type::Int),
+                Box::new(Type::Arrow(Box::new(Type::Bool), Box::new(Type::Real)))
+            )
+        );
+        assert_eq!(ty.to_string(), "int -> bool -> real");
+
+        let (ty, _) = parse_type_str("(int -> bool) -> real");
+        assert_eq!(
+            ty,
+            Type::Arrow(
+                Box::new(Type::Arrow(Box::new(Type::Int), Box::new(Type::Bool))),
+                Box::new(Type::Real)
+            )
+        );
+        assert_eq!(ty.to_string(), "(int -> bool) -> real");
+
+        let (ty, _) = parse_type_str("int -> (bool -> real)");
+        assert_eq!(ty.to_string(), "int -> bool -> real");
+    }
+
+    #[test]
+    fn test_list_types() {
+        let (ty, span) = parse_type_str("int list");
+        assert_eq!(ty, Type::List(Box::new(Type::Int)));
+        assert_eq!(span.range, 0..8);
+
+        let (ty, span) = parse_type_str("int list list");
+        assert_eq!(ty, Type::List(Box::new(Type::List(Box::new(Type::Int)))));
+        assert_eq!(span.range, 0..13);
+        assert_eq!(ty.to_string(), "int list list");
+
+        let (ty, _) = parse_type_str("int list -> (bool -> real) list");
+        assert_eq!(ty.to_string(), "int list -> (bool -> real) list");
+        assert_eq!(
+            parse_type_str("(int, char) list").0.to_string(),
+            "(int, char) list"
+        );
+
+        // `list` is not reserved outside of types.
+        assert_eq!(parse("list 1").0.to_string(), "(list 1)");
+    }
+
+    #[test]
+    fn test_type_variables() {
+        let (ty, span) = parse_type_str("'a -> 'b list");
+        assert_eq!(span.range, 0..13);
+        assert_eq!(
+            ty,
+            Type::Arrow(
+                Box::new(Type::Var(Ident::from("a"))),
+                Box::new(Type::List(Box::new(Type::Var(Ident::from("b")))))
+            )
+        );
+        assert_eq!(ty.to_string(), "'a -> 'b list");
+
+        let decls = parse_decls("fun id (x : 'a) : 'a = x val c : char = 'a'");
+        assert_eq!(decls[0].0.to_string(), "fun id (x : 'a) : 'a = x");
+        assert_eq!(decls[1].0.to_string(), "val c : char = 'a'");
+    }
+
+    #[test]
+    fn test_parenthesized_types() {
+        assert_eq!(parse_type_str("(int)"), (Type::Int, Span::new(0, 0..5)));
+        assert_eq!(parse_type_str("((bool))"), (Type::Bool, Span::new(0, 0..8)));
+        assert_eq!(
+            parse_type_str("int -> (bool)").0,
+            Type::Arrow(Box::new(Type::Int), Box::new(Type::Bool))
+        );
+
+        let tokens = Lexer::new(SourceId::default(), "(int", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let err = Parser::new(tokens).parse_type().unwrap_err();
+        let ParseError::ExpectedDelimiter {
+            expected: Token::RParen,
+            open_span,
+            end_span,
+            ..
+        } = err
+        else {
+            panic!("expected unclosed paren error");
+        };
+        assert_eq!(open_span, SourceSpan::new(0.into(), 1));
+        assert_eq!(end_span, SourceSpan::new(4.into(), 0));
+    }
+
+    #[test]
+    fn test_recovering_closes_missing_paren() {
+        let src = "val x = (1 + 2 fun f y = (y; (y, 3) val z = 1";
+        let tokens = Lexer::new(SourceId::default(), src, LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let (decls, errors) = Parser::new(tokens).parse_program_recovering();
+
+        assert_eq!(decls.len(), 3);
+        assert_eq!(decls[0].0.to_string(), "val x = (1 + 2)");
+        assert_eq!(decls[0].1.range, 0..14);
+        assert_eq!(decls[1].0.to_string(), "fun f y = (y; (y, 3))");
+        assert_eq!(errors.len(), 2);
+        let ParseError::ExpectedDelimiter {
+            open_span,
+            end_span,
+            ..
+        } = &errors[0]
+        else {
+            panic!("expected missing paren error");
+        };
+        assert_eq!(*open_span, SourceSpan::new(8.into(), 1));
+        assert_eq!(*end_span, SourceSpan::new(15.into(), 3));
+
+        // Outside recovering mode the missing paren is still a hard error.
+        let tokens = Lexer::new(SourceId::default(), src, LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        assert!(Parser::new(tokens).parse_program().is_err());
+    }
+
+    #[test]
+    fn test_recovering_reports_multiple_errors() {
+        let src = "fun f x = x + val y = 1 fun g = ) val z : = 2 fun h y = y";
+        let tokens = Lexer::new(SourceId::default(), src, LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let (decls, errors) = Parser::new(tokens).parse_program_recovering();
+
+        assert_eq!(errors.len(), 3);
+        assert!(matches!(errors[0], ParseError::ExpectedPrimary { .. }));
+        assert!(matches!(errors[1], ParseError::ExpectedPrimary { .. }));
+        assert!(matches!(errors[2], ParseError::ExpectedType { .. }));
+
+        let names: Vec<_> = decls
+            .iter()
+            .map(|(decl, _)| match decl {
+                Decl::Func(func) => func.name.0.to_string(),
+                Decl::Val(val) => val.name.0.to_string(),
+                Decl::FuncGroup(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(names, ["y", "h"]);
+    }
+
+    #[test]
+    fn test_expr_display() {
+        let cases = [
+            ("1 + 2 * 3", "(1 + (2 * 3))"),
+            ("1 - 2 - 3", "((1 - 2) - 3)"),
+            ("if c then a else b", "(if c then a else b)"),
+            ("f x y", "((f x) y)"),
+            ("not ~x = &mut y", "((not (~x)) = (&mut y))"),
+            ("(1, &x)", "(1, (&x))"),
+            (
+                "let val x : int = 1 while x < 3 do x := x + 1 in x end",
+                "let val x : int = 1; while (x < 3) do x := (x + 1) in x end",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).0.to_string(), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_cons_right_associative() {
+        let (expr, _) = parse("1 :: 2 :: xs");
+        let Expr::Binary { left, op, right } = expr else {
+            panic!("expected cons");
+        };
+        assert_eq!(op.0, BinaryOp::Cons);
+        assert_eq!(left.0, Expr::Literal(Literal::Int(1)));
+        assert!(matches!(
+            right.0,
+            Expr::Binary {
+                op: (BinaryOp::Cons, _),
+                ..
+            }
+        ));
+
+        assert_eq!(parse("1 :: 2 :: xs").0.to_string(), "(1 :: (2 :: xs))");
+        assert_eq!(
+            parse("x + 1 :: xs = ys").0.to_string(),
+            "(((x + 1) :: xs) = ys)"
+        );
+    }
+
+    #[test]
+    fn test_slash_percent_operators() {
+        let (expr, _) = parse("a / b % c");
+        let Expr::Binary { left, op, .. } = expr else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(op.0, BinaryOp::Rem);
+        assert!(matches!(
+            left.0,
+            Expr::Binary {
+                op: (BinaryOp::Div, _),
+                ..
+            }
+        ));
+        assert_eq!(parse("a div b mod c").0.to_string(), "((a div b) mod c)");
+    }
+
+    #[test]
+    fn test_unclosed_paren_points_at_stop() {
+        let tokens = Lexer::new(SourceId::default(), "(1 + 2", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        let ParseError::ExpectedDelimiter {
+            open_span,
+            end_span,
+            ..
+        } = err
+        else {
+            panic!("expected delimiter error");
+        };
+        assert_eq!(open_span, SourceSpan::new(0.into(), 1));
+        assert_eq!(end_span, SourceSpan::new(6.into(), 0));
+
+        let tokens = Lexer::new(SourceId::default(), "(1, 2 in", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::ExpectedDelimiter { end_span, .. } if end_span == SourceSpan::new(6.into(), 2)
+        ));
+    }
+
+    #[test]
+    fn test_structural_eq_ignores_spans() {
+        let (a, _) = parse("let val x = 1 in f x + 2 end");
+        let (b, _) = parse("let\n    val x =\n        1\nin\n    f x\n        + 2\nend");
+        assert_ne!(a, b);
+        assert!(a.structurally_eq(&b));
+        assert!(!a.structurally_eq(&parse("let val x = 1 in f x + 3 end").0));
+
+        let a = parse_decls("fun f (x : int) = x val y = 1");
+        let b = parse_decls("fun  f  ( x : int )  =  x\nval y=1");
+        assert!(a[0].0.structurally_eq(&b[0].0));
+        assert!(a[1].0.structurally_eq(&b[1].0));
+        assert!(!a[0].0.structurally_eq(&b[1].0));
+    }
+
+    #[test]
+    fn test_keyword_as_ident() {
+        let tokens = Lexer::new(SourceId::default(), "val let = 1", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let err = Parser::new(tokens).parse_program().unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::ReservedKeywordAsIdent {
+                keyword: Token::KwLet,
+                ..
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "`let` is a reserved keyword and cannot be used as a name"
+        );
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let (expr, _) = parse("a || b && c");
+        let Expr::Binary { op, right, .. } = expr else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(op.0, BinaryOp::LogicalOr);
+        assert!(op.0.is_short_circuit());
+        let Expr::Binary { op, .. } = right.0 else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(op.0, BinaryOp::LogicalAnd);
+        assert!(op.0.is_short_circuit());
+        assert!(!BinaryOp::Add.is_short_circuit());
+    }
+
+    #[test]
+    fn test_andalso_orelse_aliases() {
+        let (words, _) = parse("a andalso b orelse not c");
+        let (symbols, _) = parse("a && b || not c");
+        assert!(words.structurally_eq(&symbols));
+        assert_eq!(words.to_string(), "((a && b) || (not c))");
+
+        // Only the whole word is an alias.
+        assert_eq!(parse("f andalsoo").0.to_string(), "(f andalsoo)");
+    }
+
+    #[test]
+    fn test_chained_comparison() {
+        let tokens = Lexer::new(SourceId::default(), "a < b < c", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        let err = Parser::new(tokens).parse_code().unwrap_err();
+        let ParseError::ChainedComparison { span } = err else {
+            panic!("expected chained comparison error");
+        };
+        assert_eq!(span, SourceSpan::new(0.into(), 7));
+
+        assert_eq!(parse("(a < b) = c").0.to_string(), "((a < b) = c)");
+        assert_eq!(parse("a < (b = c)").0.to_string(), "(a < (b = c))");
+    }
+
+    #[test]
+    fn test_unary_precedence() {
+        let cases = [
+            ("not a = b", "((not a) = b)"),
+            ("not (a = b)", "(not (a = b))"),
+            ("not a && b", "((not a) && b)"),
+            ("~x * y", "((~x) * y)"),
+            ("~x < y", "((~x) < y)"),
+            ("not not a", "(not (not a))"),
+            ("~2 ^ 3", "((~2) ^ 3)"),
+            ("~(2 ^ 3)", "(~(2 ^ 3))"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).0.to_string(), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_nesting_too_deep() {
+        let inputs = [
+            format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000)),
+            format!("{}x", "~".repeat(10_000)),
+            format!("{}1{}", "let in ".repeat(10_000), " end".repeat(10_000)),
+            format!("{}xs", "1 :: ".repeat(100_000)),
+            format!("{}2", "2 ^ ".repeat(100_000)),
+        ];
+        for input in inputs {
+            let tokens = Lexer::new(SourceId::default(), &input, LexerConfig::default())
+                .tokenize()
+                .unwrap();
+            let err = Parser::new(tokens).parse_code().unwrap_err();
+            assert!(
+                matches!(err, ParseError::NestingTooDeep { .. }),
+                "input starting {:?}",
+                &input[..10]
+            );
+        }
+    }
+
+    #[test]
+    fn test_realistic_nesting_within_limit() {
+        let n = DEFAULT_MAX_DEPTH - 10;
+        let inputs = [
+            format!("{}1{}", "(".repeat(n), ")".repeat(n)),
+            format!("{}1{}", "[".repeat(n), "]".repeat(n)),
+            format!("{}1{}", "let val x = ".repeat(n), " in x end".repeat(n)),
+            format!("{}0", "if c then 1 else ".repeat(n)),
+            format!("{}x{}", "f (".repeat(n), ")".repeat(n)),
+            format!("{}0{}", "(a; ".repeat(n), ")".repeat(n)),
+            format!("{}0{}", "(x := ".repeat(n), "; x)".repeat(n)),
+            format!("{}0{}", "let fun f x = ".repeat(n), " in f end".repeat(n)),
+        ];
+        for input in inputs {
+            let tokens = Lexer::new(SourceId::default(), &input, LexerConfig::default())
+                .tokenize()
+                .unwrap();
+            assert!(
+                Parser::new(tokens).parse_code().is_ok(),
+                "input starting {:?}",
+                &input[..20]
+            );
+        }
+    }
+
+    #[test]
+    fn test_max_depth_is_configurable() {
+        let tokens = Lexer::new(SourceId::default(), "((1))", LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        assert!(Parser::new(tokens.clone()).parse_code().is_ok());
+        let err = Parser::new(tokens)
+            .with_max_depth(2)
+            .parse_code()
+            .unwrap_err();
+        let ParseError::NestingTooDeep { span } = err else {
+            panic!("expected nesting error");
+        };
+        assert_eq!(span, SourceSpan::new(2.into(), 1));
+    }
+
+    #[test]
+    fn test_precedence_table() {
+        assert!(precedence(BinaryOp::Mul).0 > precedence(BinaryOp::Add).0);
+        assert!(precedence(BinaryOp::Add).0 > precedence(BinaryOp::Cons).0);
+        assert!(precedence(BinaryOp::LogicalAnd).0 > precedence(BinaryOp::LogicalOr).0);
+        assert_eq!(precedence(BinaryOp::Less).1, Associativity::None);
+        assert_eq!(precedence(BinaryOp::Eq), precedence(BinaryOp::GreaterEq));
+        assert_eq!(precedence(BinaryOp::Cons).1, Associativity::Right);
+        assert_eq!(precedence(BinaryOp::Sub).1, Associativity::Left);
+        assert!(precedence(BinaryOp::Pow).0 > precedence(BinaryOp::Mul).0);
+        assert_eq!(precedence(BinaryOp::Pow).1, Associativity::Right);
+    }
+
+    #[test]
+    fn test_power_operator() {
+        let cases = [
+            ("2 ^ 3 ^ 2", "(2 ^ (3 ^ 2))"),
+            ("2 * 3 ^ 2", "(2 * (3 ^ 2))"),
+            ("2 ^ 3 * 2", "((2 ^ 3) * 2)"),
+            ("f x ^ 2", "((f x) ^ 2)"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).0.to_string(), expected, "input: {input}");
+        }
+
+        let chain = format!("{}2", "2 ^ ".repeat(10));
+        let expected = format!("{}2{}", "(2 ^ ".repeat(10), ")".repeat(10));
+        assert_eq!(parse(&chain).0.to_string(), expected);
+    }
+
+    #[test]
+    fn test_decl_display() {
+        let decls = parse_decls("fun f (x : int) y : int -> int = x + y val z : real = 1.5");
+        assert_eq!(
+            decls[0].0.to_string(),
+            "fun f (x : int) y : int -> int = (x + y)"
+        );
+        assert_eq!(decls[1].0.to_string(), "val z : real = 1.5");
+
+        let reparsed = parse_decls(&decls[0].0.to_string());
+        assert!(reparsed[0].0.structurally_eq(&decls[0].0));
+    }
+
+    #[test]
+    fn test_let_display_round_trips() {
+        let src = "fun f x = let x := 1; y := 2 in x end \
+                   val z = let val a = g; b := 2; val c = a in c end";
+        let decls = parse_decls(src);
+        assert_eq!(
+            decls[1].0.to_string(),
+            "val z = let val a = g; b := 2; val c = a in c end"
+        );
+
+        let rendered: Vec<_> = decls.iter().map(|(decl, _)| decl.to_string()).collect();
+        let reparsed = parse_decls(&rendered.join("\n"));
+        assert_eq!(reparsed.len(), decls.len());
+        for (a, b) in decls.iter().zip(&reparsed) {
+            assert!(a.0.structurally_eq(&b.0), "{}", a.0);
+        }
+    }
+
+    #[test]
+    fn test_large_program_round_trips() {
+        let src: String = (0..2_000)
+            .map(|i| format!("fun f{i} (x : int) y = if x < {i} then x * y else f{i} (x - 1) y\n"))
+            .collect();
+        let decls = parse_decls(&src);
+        assert_eq!(decls.len(), 2_000);
+
+        let rendered: Vec<_> = decls.iter().map(|(decl, _)| decl.to_string()).collect();
+        let reparsed = parse_decls(&rendered.join("\n"));
+        assert_eq!(reparsed.len(), decls.len());
+        for (a, b) in decls.iter().zip(&reparsed) {
+            assert!(a.0.structurally_eq(&b.0), "{}", a.0);
+        }
+    }
+
+    #[test]
+    fn test_unexpected_eof() {
+        for input in [
+            "1 +",
+            "if x then",
+            "f (1,",
+            "let val x = 1 while x do",
+            "fun f (x :",
+            "if x then 1",
+            "case x of 1",
+            "let val x",
+            "[1, 2",
+            "(x := 0; while x",
+        ] {
+            let tokens = Lexer::new(SourceId::default(), input, LexerConfig::default())
+                .tokenize()
+                .unwrap();
+            let err = Parser::new(tokens).parse_repl_item().unwrap_err();
+            let ParseError::UnexpectedEOF { span } = err else {
+                panic!("expected end of input error for {input:?}, got {err:?}");
+            };
+            assert_eq!(span, SourceSpan::new(input.len().into(), 0));
+        }
+    }
+
+    #[test]
+    fn test_unexpected_token_messages() {
+        let message = |input| {
+            let tokens = Lexer::new(SourceId::default(), input, LexerConfig::default())
+                .tokenize()
+                .unwrap();
+            Parser::new(tokens).parse_code().unwrap_err().to_string()
+        };
+        assert_eq!(
+            message("if x then 1 then 2"),
+            "expected `else`, found `then`"
+        );
+        assert_eq!(message("if x then 1"), "unexpected end of input");
+        assert_eq!(message("(x := 1)"), "expected `;`, found `)`");
+
+        assert_eq!(one_of(&[TokenKind::RParen, TokenKind::Comma]), "`)` or `,`");
+        assert_eq!(
+            one_of(&[TokenKind::Int, TokenKind::Ident, TokenKind::RParen]),
+            "integer literal, identifier or `)`"
+        );
     }
 }
@@ -1,5 +1,5 @@
-use miette::SourceSpan;
-use std::ops::Range;
+use miette::{NamedSource, SourceSpan};
+use std::{fmt, ops::Range};
 
 pub type SourceId = usize;
 
@@ -18,12 +18,42 @@ impl Default for Span {
     }
 }
 
+/// Orders by source, then by start, then by end, which sorts diagnostics into
+/// the order they appear in each file.
+impl Ord for Span {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.src, self.range.start, self.range.end).cmp(&(
+            other.src,
+            other.range.start,
+            other.range.end,
+        ))
+    }
+}
+
+impl PartialOrd for Span {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Span {
     #[inline]
     pub fn new(src: SourceId, range: Range<usize>) -> Self {
         Self { src, range }
     }
 
+    /// A zero-width span at `offset`, e.g. for pointing at the end of input.
+    #[inline]
+    pub fn point(src: SourceId, offset: usize) -> Self {
+        Self::new(src, offset..offset)
+    }
+
+    /// The `len` bytes starting at `start`.
+    #[inline]
+    pub fn from_len(src: SourceId, start: usize, len: usize) -> Self {
+        Self::new(src, start..start + len)
+    }
+
     #[inline]
     pub fn start(&self) -> usize {
         self.range.start
@@ -44,6 +74,31 @@ impl Span {
         self.range.is_empty()
     }
 
+    /// Whether the byte at `offset` lies within the span. The end is exclusive,
+    /// so an empty span contains nothing.
+    #[inline]
+    pub fn contains(&self, offset: usize) -> bool {
+        self.range.contains(&offset)
+    }
+
+    /// Whether the two spans share at least one byte.
+    #[inline]
+    pub fn overlaps(&self, other: &Span) -> bool {
+        debug_assert_eq!(
+            self.src, other.src,
+            "cannot compare spans from different sources"
+        );
+
+        self.range.start < other.range.end && other.range.start < self.range.end
+    }
+
+    /// Merge two spans, or `None` if they come from different sources. Prefer
+    /// this over [`Span::merge`] when the spans are not known to share a source.
+    #[inline]
+    pub fn try_merge(self, other: Self) -> Option<Self> {
+        (self.src == other.src).then(|| self.merge(other))
+    }
+
     /// Merge two spans that originate from the same source.
     #[inline]
     pub fn merge(self, other: Self) -> Self {
@@ -59,6 +114,35 @@ impl Span {
     }
 }
 
+/// Serialized as `{ "src": .., "start": .., "end": .. }`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Span {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("Span", 3)?;
+        state.serialize_field("src", &self.src)?;
+        state.serialize_field("start", &self.range.start)?;
+        state.serialize_field("end", &self.range.end)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Span {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Repr {
+            src: SourceId,
+            start: usize,
+            end: usize,
+        }
+
+        let Repr { src, start, end } = Repr::deserialize(deserializer)?;
+        Ok(Span::new(src, start..end))
+    }
+}
+
 impl From<Span> for SourceSpan {
     #[inline]
     fn from(span: Span) -> Self {
@@ -66,8 +150,122 @@ impl From<Span> for SourceSpan {
     }
 }
 
+/// Every file known to the frontend, indexed by the [`SourceId`] it was assigned.
+#[derive(Debug, Clone)]
+pub struct SourceDatabase {
+    files: Vec<SourceFile>,
+    tab_width: usize,
+}
+
+impl Default for SourceDatabase {
+    fn default() -> Self {
+        Self {
+            files: Vec::new(),
+            tab_width: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SourceFile {
+    name: String,
+    text: String,
+    /// Byte offset at which each line starts.
+    line_starts: Vec<usize>,
+}
+
+impl SourceDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a tab as `tab_width` columns in [`SourceDatabase::location`]
+    /// instead of the default of one.
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width;
+        self
+    }
+
+    /// Register a file and return the id to use when lexing it.
+    pub fn add_file(&mut self, name: impl Into<String>, contents: impl Into<String>) -> SourceId {
+        let text = contents.into();
+        let line_starts = line_starts(&text);
+        self.files.push(SourceFile {
+            name: name.into(),
+            text,
+            line_starts,
+        });
+        self.files.len() - 1
+    }
+
+    pub fn name(&self, src: SourceId) -> &str {
+        &self.files[src].name
+    }
+
+    pub fn contents(&self, src: SourceId) -> &str {
+        &self.files[src].text
+    }
+
+    /// A miette source for `src`, so diagnostics render with the file name.
+    pub fn named_source(&self, src: SourceId) -> NamedSource<String> {
+        let file = &self.files[src];
+        NamedSource::new(&file.name, file.text.clone())
+    }
+
+    /// The 1-based line and column of the start of `span`.
+    ///
+    /// Columns count characters, not bytes, and a tab counts as the configured
+    /// tab width.
+    pub fn location(&self, span: &Span) -> (usize, usize) {
+        let file = &self.files[span.src];
+        let offset = span.start().min(file.text.len());
+        let line = file.line_starts.partition_point(|&start| start <= offset) - 1;
+        let line_start = file.line_starts[line];
+        let column: usize = file.text[line_start..offset]
+            .chars()
+            .map(|c| if c == '\t' { self.tab_width } else { 1 })
+            .sum();
+        (line + 1, column + 1)
+    }
+}
+
+/// Byte offset at which each line of `text` starts. A line ends at `\n`,
+/// `\r\n` or a lone `\r`, so Windows and classic Mac files count lines the same
+/// way as Unix ones.
+fn line_starts(text: &str) -> Vec<usize> {
+    let bytes = text.as_bytes();
+    let mut starts = vec![0];
+    for (i, &byte) in bytes.iter().enumerate() {
+        let is_break = match byte {
+            b'\n' => true,
+            b'\r' => bytes.get(i + 1) != Some(&b'\n'),
+            _ => false,
+        };
+        if is_break {
+            starts.push(i + 1);
+        }
+    }
+    starts
+}
+
 pub type Spanned<T> = (T, Span);
 
+/// Transform the value of a spanned item, keeping its span.
+#[inline]
+pub fn map_spanned<T, U>((value, span): Spanned<T>, f: impl FnOnce(T) -> U) -> Spanned<U> {
+    (f(value), span)
+}
+
+/// Displays the value of a spanned item, leaving out its span, since a
+/// `Spanned<T>` tuple cannot implement `Display` itself.
+pub struct DisplaySpanned<'a, T>(pub &'a Spanned<T>);
+
+impl<T: fmt::Display> fmt::Display for DisplaySpanned<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.0.fmt(f)
+    }
+}
+
 pub trait SpannedExt {
     fn span(&self) -> Span;
 }
@@ -78,3 +276,140 @@ impl<T> SpannedExt for Spanned<T> {
         self.1.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location_multi_line() {
+        let mut map = SourceDatabase::new();
+        let src = map.add_file("main.mon", "val x = 1\nval y =\n  x + 1\n");
+        assert_eq!(map.location(&Span::new(src, 0..3)), (1, 1));
+        assert_eq!(map.location(&Span::new(src, 4..5)), (1, 5));
+        assert_eq!(map.location(&Span::new(src, 10..13)), (2, 1));
+        assert_eq!(map.location(&Span::new(src, 20..21)), (3, 3));
+        assert_eq!(map.location(&Span::new(src, 28..28)), (4, 1));
+    }
+
+    #[test]
+    fn test_location_line_endings() {
+        let mut map = SourceDatabase::new();
+        let src = map.add_file("main.mon", "val x = 1\r\nval y =\r\n  x + 1\r\n");
+        assert_eq!(map.location(&Span::new(src, 4..5)), (1, 5));
+        assert_eq!(map.location(&Span::new(src, 11..14)), (2, 1));
+        assert_eq!(map.location(&Span::new(src, 22..23)), (3, 3));
+        assert_eq!(map.location(&Span::point(src, 29)), (4, 1));
+
+        let src = map.add_file("old_mac.mon", "val x = 1\rx\n\ry");
+        assert_eq!(map.location(&Span::point(src, 10)), (2, 1));
+        assert_eq!(map.location(&Span::point(src, 13)), (4, 1));
+    }
+
+    #[test]
+    fn test_location_counts_chars() {
+        let mut map = SourceDatabase::new();
+        let src = map.add_file("main.mon", "val é = 'λ' x");
+        let offset = map.contents(src).rfind('x').unwrap();
+        assert_eq!(offset, 14);
+        assert_eq!(map.location(&Span::new(src, offset..offset + 1)), (1, 13));
+    }
+
+    #[test]
+    fn test_location_tab_width() {
+        let text = "val x =\n\t\tx + 1";
+        let offset = text.rfind('x').unwrap();
+
+        let mut map = SourceDatabase::new();
+        let src = map.add_file("main.mon", text);
+        assert_eq!(map.location(&Span::point(src, offset)), (2, 3));
+
+        let mut map = SourceDatabase::new().with_tab_width(4);
+        let src = map.add_file("main.mon", text);
+        assert_eq!(map.location(&Span::point(src, offset)), (2, 9));
+    }
+
+    #[test]
+    fn test_source_database_lookup() {
+        let mut db = SourceDatabase::new();
+        let main = db.add_file("main.mon", "val x = 1");
+        let lib = db.add_file("lib.mon", "fun id x = x");
+        assert_ne!(main, lib);
+        assert_eq!(db.name(main), "main.mon");
+        assert_eq!(db.contents(main), "val x = 1");
+        assert_eq!(db.name(lib), "lib.mon");
+        assert_eq!(db.contents(lib), "fun id x = x");
+        assert_eq!(db.named_source(lib).name(), "lib.mon");
+    }
+
+    #[test]
+    fn test_span_constructors() {
+        let point = Span::point(1, 7);
+        assert_eq!(point, Span::new(1, 7..7));
+        assert!(point.is_empty());
+
+        let span = Span::from_len(1, 4, 3);
+        assert_eq!(span, Span::new(1, 4..7));
+        assert_eq!(span.len(), 3);
+    }
+
+    #[test]
+    fn test_display_spanned() {
+        use crate::lexer::Token;
+
+        let token = (Token::ColonEq, Span::new(0, 2..4));
+        assert_eq!(DisplaySpanned(&token).to_string(), ":=");
+        assert_eq!(format!("`{}`", DisplaySpanned(&token)), "`:=`");
+    }
+
+    #[test]
+    fn test_map_spanned() {
+        let spanned = (21, Span::new(0, 3..5));
+        let (value, span) = map_spanned(spanned, |x| x * 2);
+        assert_eq!(value, 42);
+        assert_eq!(span, Span::new(0, 3..5));
+    }
+
+    #[test]
+    fn test_span_contains_and_overlaps() {
+        let span = Span::new(0, 4..7);
+        assert!(!span.contains(3));
+        assert!(span.contains(4));
+        assert!(span.contains(6));
+        assert!(!span.contains(7));
+        assert!(!Span::point(0, 4).contains(4));
+
+        assert!(span.overlaps(&Span::new(0, 6..9)));
+        assert!(span.overlaps(&Span::new(0, 0..10)));
+        assert!(!span.overlaps(&Span::new(0, 7..9)));
+        assert!(!span.overlaps(&Span::new(0, 0..4)));
+    }
+
+    #[test]
+    fn test_try_merge() {
+        let merged = Span::new(1, 4..7).try_merge(Span::new(1, 2..5));
+        assert_eq!(merged, Some(Span::new(1, 2..7)));
+        assert_eq!(Span::new(1, 4..7).try_merge(Span::new(2, 4..7)), None);
+        assert_eq!(Span::default().try_merge(Span::new(1, 0..1)), None);
+    }
+
+    #[test]
+    fn test_span_ordering() {
+        let mut spans = vec![
+            Span::new(1, 0..2),
+            Span::new(0, 5..6),
+            Span::new(0, 2..9),
+            Span::new(0, 2..4),
+        ];
+        spans.sort();
+        assert_eq!(
+            spans,
+            [
+                Span::new(0, 2..4),
+                Span::new(0, 2..9),
+                Span::new(0, 5..6),
+                Span::new(1, 0..2),
+            ]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use syntax::{
+    SyntaxError,
+    ast::{BinaryOp, Decl, Expr, Literal},
+    parse_line, parse_program, parse_str,
+    parser::{ParseError, ReplItem},
+    span::SourceId,
+};
+
+#[test]
+fn parses_full_program() {
+    let src = "let val x = 0x10 (* sixteen *) in if x > 1 then f x else 0 end";
+    let (expr, span) = parse_str(SourceId::default(), src).unwrap();
+    assert_eq!(span.range, 0..src.len());
+
+    let Expr::Let { stmts, expr } = expr else {
+        panic!("expected let expression");
+    };
+    assert_eq!(stmts.len(), 1);
+    let Expr::If { condition, .. } = expr.0 else {
+        panic!("expected if expression");
+    };
+    let Expr::Binary { op, right, .. } = condition.0 else {
+        panic!("expected comparison");
+    };
+    assert_eq!(op.0, BinaryOp::Greater);
+    assert_eq!(right.0, Expr::Literal(Literal::Int(1)));
+}
+
+#[test]
+fn reports_lex_errors() {
+    let errors = parse_str(SourceId::default(), "1 $ 2 #").unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().all(|e| matches!(e, SyntaxError::Lex(_))));
+}
+
+#[test]
+fn reports_parse_errors() {
+    let errors = parse_str(SourceId::default(), "(1 + 2").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0], SyntaxError::Parse(_)));
+
+    let errors = parse_str(SourceId::default(), "1 )").unwrap_err();
+    assert!(matches!(errors[0], SyntaxError::Parse(_)));
+}
+
+#[test]
+fn parses_repl_expression_line() {
+    let item = parse_line(SourceId::default(), "f 1 + 2").unwrap();
+    let ReplItem::Expr((expr, _)) = item else {
+        panic!("expected expression");
+    };
+    assert_eq!(expr.to_string(), "((f 1) + 2)");
+}
+
+#[test]
+fn parses_repl_declaration_line() {
+    let item = parse_line(SourceId::default(), "fun inc x = x + 1").unwrap();
+    assert!(matches!(item, ReplItem::Decl((Decl::Func(_), _))));
+
+    let item = parse_line(SourceId::default(), "val y = 2").unwrap();
+    assert!(matches!(item, ReplItem::Decl((Decl::Val(_), _))));
+}
+
+#[test]
+fn rejects_repl_trailing_tokens() {
+    let errors = parse_line(SourceId::default(), "val y = 2 )").unwrap_err();
+    assert!(matches!(
+        errors[..],
+        [SyntaxError::Parse(ParseError::UnexpectedToken { .. })]
+    ));
+
+    let errors = parse_line(SourceId::default(), "1 + 2 val x = 3").unwrap_err();
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn parses_empty_program() {
+    for src in ["", "   \n\t ", "(* nothing here *)\n"] {
+        assert_eq!(parse_program(SourceId::default(), src).unwrap(), []);
+    }
+
+    // An expression, unlike a program, cannot be empty.
+    let errors = parse_str(SourceId::default(), "  ").unwrap_err();
+    assert!(matches!(
+        errors[..],
+        [SyntaxError::Parse(ParseError::UnexpectedEOF { .. })]
+    ));
+}
+
+#[test]
+fn reports_every_program_error() {
+    let decls = parse_program(SourceId::default(), "val x = 1 fun f y = y").unwrap();
+    assert_eq!(decls.len(), 2);
+
+    let errors = parse_program(SourceId::default(), "val x = val y = ) fun f = 1").unwrap_err();
+    assert_eq!(errors.len(), 2);
+}
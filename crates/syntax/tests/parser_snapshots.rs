@@ -0,0 +1,42 @@
+//! Snapshots of parsed programs, rendered through `Display` so they hold no
+//! spans or interner addresses and stay stable as unrelated code moves.
+
+use syntax::{parse_program, parse_str, span::SourceId};
+
+fn program(src: &str) -> String {
+    parse_program(SourceId::default(), src)
+        .unwrap()
+        .iter()
+        .map(|(decl, _)| format!("{decl}\n"))
+        .collect()
+}
+
+fn expr(src: &str) -> String {
+    parse_str(SourceId::default(), src).unwrap().0.to_string()
+}
+
+#[test]
+fn snapshot_arithmetic_precedence() {
+    insta::assert_snapshot!(expr("1 + 2 * 3 - f x y div 4 :: xs"));
+}
+
+#[test]
+fn snapshot_control_flow() {
+    insta::assert_snapshot!(expr(
+        "if n <= 1 andalso not done then [n] else let val m = n - 1 in (g m; m * n) end"
+    ));
+}
+
+#[test]
+fn snapshot_program() {
+    insta::assert_snapshot!(program(
+        "val origin : (int, int) = (0, 0)
+         fun fact (n : int) : int = if n = 0 then 1 else n * fact (n - 1)
+         fun sum xs acc = let
+             val total = acc;
+             fun step x = x + 1
+             while total < xs do total := step total
+         in total : int end
+         val names : char list = ['a', '\\n', 'z']"
+    ));
+}
@@ -0,0 +1,58 @@
+//! Lexes and parses every program in `tests/programs/` and compares the
+//! results with the checked-in `.tokens` and `.ast` files next to it.
+//!
+//! Run with `UPDATE_GOLDEN=1` to rewrite the expectations after an intended
+//! change, then review the diff.
+
+use std::{env, fs, path::Path};
+use syntax::{
+    lexer::{Lexer, LexerConfig, debug_tokens},
+    parse_program,
+    span::SourceId,
+};
+
+fn check(path: &Path, actual: &str) -> bool {
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(path, actual).unwrap();
+        return true;
+    }
+    let expected = fs::read_to_string(path).unwrap_or_default();
+    if expected != actual {
+        eprintln!("{} is out of date:\n{actual}", path.display());
+        return false;
+    }
+    true
+}
+
+#[test]
+fn golden_programs() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let mut sources: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "mon"))
+        .collect();
+    sources.sort();
+    assert!(!sources.is_empty(), "no programs in {}", dir.display());
+
+    let mut ok = true;
+    for path in &sources {
+        let src = fs::read_to_string(path).unwrap();
+
+        let tokens = Lexer::new(SourceId::default(), &src, LexerConfig::default())
+            .tokenize()
+            .unwrap();
+        ok &= check(&path.with_extension("tokens"), &debug_tokens(&src, &tokens));
+
+        let ast: String = parse_program(SourceId::default(), &src)
+            .unwrap_or_else(|errors| panic!("{}: {errors:?}", path.display()))
+            .iter()
+            .map(|(decl, _)| format!("{decl}\n"))
+            .collect();
+        ok &= check(&path.with_extension("ast"), &ast);
+    }
+    assert!(
+        ok,
+        "golden outputs differ; rerun with UPDATE_GOLDEN=1 to accept"
+    );
+}
@@ -0,0 +1,20 @@
+#![cfg(feature = "serde")]
+
+use syntax::{
+    ast::Expr,
+    parse_str,
+    span::{SourceId, Spanned},
+};
+
+#[test]
+fn ast_json_round_trip() {
+    let src = "let val x : (int, real) = (1, 2.5) while x < 3 do x := x + 1 in f (&mut x) end";
+    let expr = parse_str(SourceId::default(), src).unwrap();
+
+    let json = serde_json::to_string(&expr).unwrap();
+    assert!(json.contains(r#"{"src":0,"start":0,"end":78}"#));
+    assert!(json.contains(r#""x""#));
+
+    let back: Spanned<Expr> = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, expr);
+}
@@ -1,3 +1 @@
-fn main() {
-    if if true { true } else { false } {}
-}
+fn main() {}